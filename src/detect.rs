@@ -0,0 +1,650 @@
+//! Rule-driven technology detection.
+//!
+//! Detection is described by a table of [`DetectionRule`]s. The defaults are
+//! embedded from `rules/default.toml`; additional rule files can be referenced
+//! from `detection.rule_files` in the config. Templates can contribute manifest
+//! rules of their own through the `detect` key of their front-matter.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::templates::TemplateInfo;
+use crate::{DetectionConfig, expand_str_path};
+
+const DEFAULT_RULES: &str = include_str!("../rules/default.toml");
+
+/// Source label used for rules compiled into the binary
+pub const EMBEDDED_SOURCE: &str = "embedded";
+
+/// Evidence entries kept per template and evidence kind
+const MAX_EVIDENCE_PER_KIND: usize = 3;
+
+/// Score contributed by a single file matching one of a rule's extensions.
+/// Manifests, directories and the OS contribute a full point each.
+const EXTENSION_WEIGHT: f64 = 0.25;
+/// Multiplier for evidence found below one of `detection.vendor_dirs`
+const VENDORED_WEIGHT: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleCategory {
+    #[default]
+    Language,
+    Tool,
+    Ide,
+}
+
+impl RuleCategory {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RuleCategory::Language => "language",
+            RuleCategory::Tool => "tool",
+            RuleCategory::Ide => "ide",
+        }
+    }
+
+    /// Category implied by a template's front-matter tags
+    fn from_tags(tags: &[String]) -> Self {
+        if tags.iter().any(|t| t == "ide") {
+            RuleCategory::Ide
+        } else if tags.iter().any(|t| t == "tool") {
+            RuleCategory::Tool
+        } else {
+            RuleCategory::Language
+        }
+    }
+}
+
+/// A single detection rule mapping project evidence to a template
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectionRule {
+    /// Template to add when the rule fires
+    pub template: String,
+    #[serde(default)]
+    pub category: RuleCategory,
+    /// Globs matched against file names (or relative paths when they contain `/`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub manifests: Vec<String>,
+    /// File extensions without the leading dot
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<String>,
+    /// Directory names
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub directories: Vec<String>,
+    /// Globs that must each match a sibling of the matched entry
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+    /// Globs that must not match any sibling of the matched entry
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forbids: Vec<String>,
+    /// Multiplier applied to the score of every match of this rule
+    #[serde(default = "default_rule_weight")]
+    pub weight: f64,
+    /// Where the rule was loaded from
+    #[serde(default, skip_deserializing)]
+    pub source: String,
+}
+
+fn default_rule_weight() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<DetectionRule>,
+}
+
+#[derive(Debug)]
+struct CompiledRule {
+    rule: DetectionRule,
+    names: GlobSet,
+    name_patterns: Vec<String>,
+    paths: GlobSet,
+    path_patterns: Vec<String>,
+    requires: Vec<GlobMatcher>,
+    forbids: GlobSet,
+}
+
+/// The effective set of detection rules
+#[derive(Debug)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+    /// Loads the embedded rules (unless disabled) followed by any configured rule files
+    pub fn load(config: &DetectionConfig) -> Result<Self> {
+        let mut rules = Vec::new();
+
+        if config.builtin_rules {
+            rules.extend(parse_rules(DEFAULT_RULES, EMBEDDED_SOURCE)?);
+        }
+
+        for file in &config.rule_files {
+            let path = expand_str_path(file)?;
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("reading detection rules from {}", path.display()))?;
+            rules.extend(parse_rules(&text, &path.display().to_string())?);
+        }
+
+        Self::from_rules(rules)
+    }
+
+    fn from_rules(rules: Vec<DetectionRule>) -> Result<Self> {
+        let rules = rules
+            .into_iter()
+            .map(compile_rule)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Adds a manifest rule for every template declaring `detect` globs in its front-matter
+    pub fn add_template_hints(&mut self, templates: &[TemplateInfo]) -> Result<()> {
+        for info in templates.iter().filter(|t| !t.meta.detect.is_empty()) {
+            let rule = DetectionRule {
+                template: info.name.clone(),
+                category: RuleCategory::from_tags(&info.meta.tags),
+                manifests: info.meta.detect.clone(),
+                extensions: Vec::new(),
+                directories: Vec::new(),
+                requires: Vec::new(),
+                forbids: Vec::new(),
+                weight: default_rule_weight(),
+                source: format!("template:{}", info.name),
+            };
+            self.rules.push(compile_rule(rule)?);
+        }
+        Ok(())
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &DetectionRule> {
+        self.rules.iter().map(|compiled| &compiled.rule)
+    }
+}
+
+fn parse_rules(text: &str, source: &str) -> Result<Vec<DetectionRule>> {
+    let file: RuleFile =
+        toml::from_str(text).with_context(|| format!("parsing detection rules from {source}"))?;
+    let mut rules = file.rules;
+    for rule in &mut rules {
+        if rule.template.trim().is_empty() {
+            return Err(anyhow!("detection rule without a template in {source}"));
+        }
+        rule.template = rule.template.trim().to_lowercase();
+        rule.source = source.to_string();
+    }
+    Ok(rules)
+}
+
+fn compile_rule(rule: DetectionRule) -> Result<CompiledRule> {
+    let mut names = GlobSetBuilder::new();
+    let mut name_patterns = Vec::new();
+    let mut paths = GlobSetBuilder::new();
+    let mut path_patterns = Vec::new();
+    for pattern in &rule.manifests {
+        let glob = build_glob(pattern, &rule)?;
+        if pattern.contains('/') {
+            paths.add(glob);
+            path_patterns.push(pattern.clone());
+        } else {
+            names.add(glob);
+            name_patterns.push(pattern.clone());
+        }
+    }
+
+    let requires = rule
+        .requires
+        .iter()
+        .map(|pattern| build_glob(pattern, &rule).map(|glob| glob.compile_matcher()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut forbids = GlobSetBuilder::new();
+    for pattern in &rule.forbids {
+        forbids.add(build_glob(pattern, &rule)?);
+    }
+
+    Ok(CompiledRule {
+        names: names.build()?,
+        name_patterns,
+        paths: paths.build()?,
+        path_patterns,
+        requires,
+        forbids: forbids.build()?,
+        rule,
+    })
+}
+
+fn build_glob(pattern: &str, rule: &DetectionRule) -> Result<Glob> {
+    Glob::new(pattern).with_context(|| {
+        format!(
+            "invalid pattern '{pattern}' in rule for '{}' ({})",
+            rule.template, rule.source
+        )
+    })
+}
+
+impl CompiledRule {
+    /// Returns which matcher of this rule an entry hits, if any
+    fn match_entry(
+        &self,
+        rel_path: &Path,
+        file_name: &str,
+        is_dir: bool,
+    ) -> Option<(EvidenceKind, String)> {
+        if is_dir {
+            return self
+                .rule
+                .directories
+                .iter()
+                .find(|d| *d == file_name)
+                .map(|d| (EvidenceKind::Directory, d.clone()));
+        }
+
+        if let Some(&index) = self.names.matches(file_name).first() {
+            return Some((EvidenceKind::Manifest, self.name_patterns[index].clone()));
+        }
+        if let Some(&index) = self.paths.matches(rel_path).first() {
+            return Some((EvidenceKind::Manifest, self.path_patterns[index].clone()));
+        }
+
+        let ext = rel_path.extension().and_then(|e| e.to_str())?;
+        self.rule
+            .extensions
+            .iter()
+            .find(|e| *e == ext)
+            .map(|e| (EvidenceKind::Extension, e.clone()))
+    }
+
+    fn needs_siblings(&self) -> bool {
+        !self.requires.is_empty() || !self.forbids.is_empty()
+    }
+
+    fn siblings_allow(&self, siblings: &[String]) -> bool {
+        let required = self
+            .requires
+            .iter()
+            .all(|matcher| siblings.iter().any(|name| matcher.is_match(name)));
+        let forbidden = siblings.iter().any(|name| self.forbids.is_match(name));
+        required && !forbidden
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvidenceKind {
+    Manifest,
+    Extension,
+    Directory,
+    Os,
+}
+
+impl EvidenceKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EvidenceKind::Manifest => "manifest",
+            EvidenceKind::Extension => "extension",
+            EvidenceKind::Directory => "directory",
+            EvidenceKind::Os => "os",
+        }
+    }
+
+    fn base_weight(self) -> f64 {
+        match self {
+            EvidenceKind::Extension => EXTENSION_WEIGHT,
+            EvidenceKind::Manifest | EvidenceKind::Directory | EvidenceKind::Os => 1.0,
+        }
+    }
+}
+
+/// Why a template was detected
+#[derive(Debug, Clone, Serialize)]
+pub struct Evidence {
+    pub kind: EvidenceKind,
+    /// The rule matcher that fired (glob, extension, directory name or OS)
+    pub matched: String,
+    /// Path relative to the scanned directory (empty for OS detection)
+    pub path: String,
+    /// Where the rule came from
+    pub source: String,
+    /// Score this match contributed
+    pub weight: f64,
+}
+
+/// A candidate template with its score and the evidence that triggered it
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectedTemplate {
+    pub template: String,
+    /// Sum of the weights of all matches
+    pub score: f64,
+    /// Whether the score reached `detection.min_score`
+    pub accepted: bool,
+    /// Total number of matching entries, including ones not kept as evidence
+    pub matches: usize,
+    pub evidence: Vec<Evidence>,
+}
+
+impl DetectedTemplate {
+    fn record(&mut self, evidence: Evidence) {
+        self.matches += 1;
+        self.score += evidence.weight;
+        let same_kind = self
+            .evidence
+            .iter()
+            .filter(|e| e.kind == evidence.kind)
+            .count();
+        if same_kind < MAX_EVIDENCE_PER_KIND {
+            self.evidence.push(evidence);
+        }
+    }
+}
+
+/// Result of scanning a directory
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Detection {
+    pub templates: BTreeMap<String, DetectedTemplate>,
+}
+
+impl Detection {
+    /// Templates whose score reached the threshold
+    pub fn names(&self) -> BTreeSet<String> {
+        self.templates
+            .values()
+            .filter(|t| t.accepted)
+            .map(|t| t.template.clone())
+            .collect()
+    }
+
+    /// Candidates that were found but scored below the threshold
+    pub fn rejected(&self) -> impl Iterator<Item = &DetectedTemplate> {
+        self.templates.values().filter(|t| !t.accepted)
+    }
+
+    fn record(&mut self, template: &str, evidence: Evidence) {
+        self.templates
+            .entry(template.to_string())
+            .or_insert_with(|| DetectedTemplate {
+                template: template.to_string(),
+                score: 0.0,
+                accepted: false,
+                matches: 0,
+                evidence: Vec::new(),
+            })
+            .record(evidence);
+    }
+}
+
+/// Detects technologies used in a directory, recording why each was picked.
+///
+/// Every match adds to its template's score; templates are accepted when the
+/// score reaches `detection.min_score`.
+pub fn detect_technologies(
+    dir: &Path,
+    config: &DetectionConfig,
+    rules: &RuleSet,
+    depth: usize,
+) -> Result<Detection> {
+    let mut detection = Detection::default();
+    let mut sibling_cache: HashMap<PathBuf, Vec<String>> = HashMap::new();
+
+    let walker = WalkBuilder::new(dir)
+        .max_depth(Some(depth.min(config.max_depth)))
+        .hidden(false)
+        .git_ignore(true)
+        .build();
+
+    for entry in walker.flatten() {
+        let path = entry.path();
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let rel_path = path.strip_prefix(dir).unwrap_or(path);
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+
+        for compiled in &rules.rules {
+            if compiled.rule.category == RuleCategory::Ide && !config.detect_ide {
+                continue;
+            }
+            let Some((kind, matched)) = compiled.match_entry(rel_path, file_name, is_dir) else {
+                continue;
+            };
+            if compiled.needs_siblings() {
+                let parent = path.parent().unwrap_or(dir).to_path_buf();
+                let siblings = sibling_cache
+                    .entry(parent)
+                    .or_insert_with_key(|parent| list_names(parent));
+                if !compiled.siblings_allow(siblings) {
+                    continue;
+                }
+            }
+            let mut weight = kind.base_weight() * compiled.rule.weight;
+            if is_vendored(rel_path, &config.vendor_dirs) {
+                weight *= VENDORED_WEIGHT;
+            }
+            detection.record(
+                &compiled.rule.template,
+                Evidence {
+                    kind,
+                    matched,
+                    path: rel_path.display().to_string(),
+                    source: compiled.rule.source.clone(),
+                    weight,
+                },
+            );
+        }
+    }
+
+    // Detect OS
+    if config.detect_os
+        && let Some(os) = current_os_template()
+    {
+        detection.record(
+            os,
+            Evidence {
+                kind: EvidenceKind::Os,
+                matched: env::consts::OS.to_string(),
+                path: String::new(),
+                source: EMBEDDED_SOURCE.to_string(),
+                weight: EvidenceKind::Os.base_weight(),
+            },
+        );
+    }
+
+    for template in detection.templates.values_mut() {
+        // Strongest evidence first
+        template.evidence.sort_by_key(|e| e.kind);
+        // Tolerate float rounding when summing fractional weights
+        template.accepted = template.score + 1e-9 >= config.min_score;
+    }
+
+    Ok(detection)
+}
+
+/// Whether a path lies below a vendored or fixture directory
+fn is_vendored(rel_path: &Path, vendor_dirs: &[String]) -> bool {
+    rel_path
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .filter_map(|c| c.as_os_str().to_str())
+        .any(|component| vendor_dirs.iter().any(|v| v == component))
+}
+
+fn current_os_template() -> Option<&'static str> {
+    if cfg!(target_os = "linux") {
+        Some("linux")
+    } else if cfg!(target_os = "macos") {
+        Some("macos")
+    } else if cfg!(target_os = "windows") {
+        Some("windows")
+    } else {
+        None
+    }
+}
+
+fn list_names(dir: &Path) -> Vec<String> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Formats a rule's matchers for human-readable output
+pub fn describe_rule(rule: &DetectionRule) -> String {
+    let mut parts = Vec::new();
+    for (label, values) in [
+        ("manifests", &rule.manifests),
+        ("extensions", &rule.extensions),
+        ("directories", &rule.directories),
+        ("requires", &rule.requires),
+        ("forbids", &rule.forbids),
+    ] {
+        if !values.is_empty() {
+            parts.push(format!("{label}: {}", values.join(", ")));
+        }
+    }
+    if (rule.weight - 1.0).abs() > f64::EPSILON {
+        parts.push(format!("weight: {}", rule.weight));
+    }
+    parts.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(template: &str) -> DetectionRule {
+        DetectionRule {
+            template: template.to_string(),
+            category: RuleCategory::Language,
+            manifests: vec![],
+            extensions: vec![],
+            directories: vec![],
+            requires: vec![],
+            forbids: vec![],
+            weight: 1.0,
+            source: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn embedded_rules_parse() {
+        let rules = parse_rules(DEFAULT_RULES, EMBEDDED_SOURCE).unwrap();
+        assert!(rules.iter().any(|r| r.template == "rust"));
+        assert!(
+            rules
+                .iter()
+                .any(|r| r.template == "vscode" && r.category == RuleCategory::Ide)
+        );
+        RuleSet::from_rules(rules).unwrap();
+    }
+
+    #[test]
+    fn manifest_with_slash_matches_relative_path() {
+        let compiled = compile_rule(DetectionRule {
+            manifests: vec!["*kotlin*/build.gradle.kts".to_string()],
+            ..rule("kotlin")
+        })
+        .unwrap();
+        let hit = compiled.match_entry(
+            Path::new("app/kotlin-lib/build.gradle.kts"),
+            "build.gradle.kts",
+            false,
+        );
+        assert_eq!(
+            hit,
+            Some((
+                EvidenceKind::Manifest,
+                "*kotlin*/build.gradle.kts".to_string()
+            ))
+        );
+        assert!(
+            compiled
+                .match_entry(Path::new("build.gradle.kts"), "build.gradle.kts", false)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn extensions_and_directories_match() {
+        let compiled = compile_rule(DetectionRule {
+            extensions: vec!["rs".to_string()],
+            directories: vec![".idea".to_string()],
+            ..rule("mixed")
+        })
+        .unwrap();
+        assert_eq!(
+            compiled.match_entry(Path::new("src/main.rs"), "main.rs", false),
+            Some((EvidenceKind::Extension, "rs".to_string()))
+        );
+        assert!(
+            compiled
+                .match_entry(Path::new("src/main.rs"), "main.rs", true)
+                .is_none()
+        );
+        assert_eq!(
+            compiled.match_entry(Path::new(".idea"), ".idea", true),
+            Some((EvidenceKind::Directory, ".idea".to_string()))
+        );
+    }
+
+    #[test]
+    fn sibling_constraints() {
+        let compiled = compile_rule(DetectionRule {
+            manifests: vec!["Makefile".to_string()],
+            requires: vec!["*.c".to_string()],
+            forbids: vec!["Cargo.toml".to_string()],
+            ..rule("cpp")
+        })
+        .unwrap();
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(compiled.siblings_allow(&names(&["Makefile", "main.c"])));
+        assert!(!compiled.siblings_allow(&names(&["Makefile"])));
+        assert!(!compiled.siblings_allow(&names(&["Makefile", "main.c", "Cargo.toml"])));
+    }
+
+    #[test]
+    fn evidence_is_capped_per_kind() {
+        let mut detection = Detection::default();
+        for i in 0..10 {
+            detection.record(
+                "rust",
+                Evidence {
+                    kind: EvidenceKind::Extension,
+                    matched: "rs".to_string(),
+                    path: format!("src/{i}.rs"),
+                    source: "test".to_string(),
+                    weight: EXTENSION_WEIGHT,
+                },
+            );
+        }
+        let rust = &detection.templates["rust"];
+        assert_eq!(rust.matches, 10);
+        assert_eq!(rust.evidence.len(), MAX_EVIDENCE_PER_KIND);
+        assert!((rust.score - 2.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn vendored_paths_are_detected() {
+        let vendor = vec!["vendor".to_string(), "testdata".to_string()];
+        assert!(is_vendored(Path::new("vendor/lib/Makefile"), &vendor));
+        assert!(is_vendored(Path::new("a/testdata/x.h"), &vendor));
+        assert!(!is_vendored(Path::new("src/vendor.rs"), &vendor));
+        assert!(!is_vendored(Path::new("vendor"), &vendor));
+    }
+
+    #[test]
+    fn rule_without_template_is_rejected() {
+        let err = parse_rules("[[rule]]\ntemplate = \"\"\n", "test").unwrap_err();
+        assert!(err.to_string().contains("without a template"));
+    }
+}
@@ -0,0 +1,110 @@
+//! Unified diffs for previewing and checking .gitignore changes.
+
+use similar::{ChangeTag, TextDiff};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Lines added and removed between two texts
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LineChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Renders a unified diff, or an empty string when the texts are equal
+pub fn unified(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    let old = with_trailing_newline(old);
+    let new = with_trailing_newline(new);
+    TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .context_radius(3)
+        .header(old_label, new_label)
+        .to_string()
+}
+
+/// Adds ANSI colors to a unified diff
+pub fn colorize(diff: &str) -> String {
+    let mut output = String::with_capacity(diff.len());
+    for line in diff.split_inclusive('\n') {
+        let color = if line.starts_with("+++") || line.starts_with("---") {
+            BOLD
+        } else if line.starts_with('+') {
+            GREEN
+        } else if line.starts_with('-') {
+            RED
+        } else if line.starts_with("@@") {
+            CYAN
+        } else {
+            output.push_str(line);
+            continue;
+        };
+        let text = line.trim_end_matches('\n');
+        output.push_str(&format!("{color}{text}{RESET}"));
+        if line.ends_with('\n') {
+            output.push('\n');
+        }
+    }
+    output
+}
+
+/// Collects the lines that were added and removed
+pub fn line_changes(old: &str, new: &str) -> LineChanges {
+    let old = with_trailing_newline(old);
+    let new = with_trailing_newline(new);
+    let mut changes = LineChanges::default();
+    for change in TextDiff::from_lines(&old, &new).iter_all_changes() {
+        let line = change.value().trim_end_matches(['\n', '\r']).to_string();
+        match change.tag() {
+            ChangeTag::Insert => changes.added.push(line),
+            ChangeTag::Delete => changes.removed.push(line),
+            ChangeTag::Equal => {}
+        }
+    }
+    changes
+}
+
+fn with_trailing_newline(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{text}\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_texts_have_no_diff() {
+        assert_eq!(unified("a\nb", "a\nb", "old", "new"), "");
+        assert_eq!(line_changes("a\nb", "a\nb"), LineChanges::default());
+    }
+
+    #[test]
+    fn reports_added_and_removed_lines() {
+        let changes = line_changes("a\nb\nc", "a\nc\nd");
+        assert_eq!(changes.added, vec!["d".to_string()]);
+        assert_eq!(changes.removed, vec!["b".to_string()]);
+
+        let diff = unified("a\nb\nc", "a\nc\nd", "old", "new");
+        assert!(diff.starts_with("--- old\n+++ new\n"));
+        assert!(diff.contains("-b\n"));
+        assert!(diff.contains("+d\n"));
+    }
+
+    #[test]
+    fn colorize_wraps_changed_lines() {
+        let colored = colorize("--- old\n+++ new\n@@ -1 +1 @@\n-b\n+d\n a\n");
+        assert!(colored.contains("\x1b[31m-b\x1b[0m\n"));
+        assert!(colored.contains("\x1b[32m+d\x1b[0m\n"));
+        assert!(colored.ends_with(" a\n"));
+    }
+}
@@ -0,0 +1,272 @@
+//! Templates in the layout of the github/gitignore repository.
+//!
+//! The repository keeps templates as `<Name>.gitignore` files at the top
+//! level, in `Global/` (editors and operating systems) and in subdirectories
+//! of `community/`. It can be read from a local checkout, a `.tar.gz` archive
+//! on disk, or an archive downloaded over HTTP.
+//!
+//! File names are mapped to template names by lowercasing them
+//! (`Python.gitignore` becomes `python`), and a few upstream names are renamed
+//! to match the embedded templates. If several files map to the same name, the
+//! top level wins over `Global/`, which wins over `community/`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use log::debug;
+
+use crate::sync::{self, SyncOptions};
+
+/// Archive of the upstream repository's default branch
+pub const DEFAULT_TARBALL: &str =
+    "https://codeload.github.com/github/gitignore/tar.gz/refs/heads/main";
+
+const EXTENSION: &str = ".gitignore";
+
+/// Upstream names that differ from the names of the embedded templates
+const RENAMES: &[(&str, &str)] = &[
+    ("c++", "cpp"),
+    ("visualstudiocode", "vscode"),
+    ("jetbrains", "intellij"),
+];
+
+/// Where to read the repository from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Checkout(PathBuf),
+    Tarball(PathBuf),
+    Url(String),
+}
+
+impl Location {
+    /// Interprets `source` as an HTTP(S) URL, a directory or an archive file
+    pub fn parse(source: &str) -> Self {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            return Location::Url(source.to_string());
+        }
+        let path = PathBuf::from(source);
+        if path.is_dir() {
+            Location::Checkout(path)
+        } else {
+            Location::Tarball(path)
+        }
+    }
+
+    /// Reads every template, keyed by normalized name
+    pub fn load(&self, options: &SyncOptions) -> Result<BTreeMap<String, String>> {
+        match self {
+            Location::Checkout(root) => read_checkout(root),
+            Location::Tarball(path) => {
+                let file =
+                    fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+                read_tarball(file).with_context(|| format!("reading {}", path.display()))
+            }
+            Location::Url(url) => {
+                let archive = sync::download(url, options)?;
+                read_tarball(archive.as_slice()).with_context(|| format!("reading {url}"))
+            }
+        }
+    }
+}
+
+/// Templates found while walking the repository, with the rank of their directory
+#[derive(Default)]
+struct Collector {
+    templates: BTreeMap<String, (u8, String)>,
+}
+
+impl Collector {
+    /// Adds the file at `path` (relative to the repository root) if it is a template
+    fn add(&mut self, path: &Path, content: String) {
+        let Some((rank, name)) = template_name(path) else {
+            return;
+        };
+        match self.templates.get(&name) {
+            Some((existing, _)) if *existing <= rank => {
+                debug!("Skipping {}: '{name}' is already defined", path.display());
+            }
+            _ => {
+                self.templates.insert(name, (rank, content));
+            }
+        }
+    }
+
+    fn finish(self) -> BTreeMap<String, String> {
+        self.templates
+            .into_iter()
+            .map(|(name, (_, content))| (name, content))
+            .collect()
+    }
+}
+
+/// The rank and normalized name of a template file, or `None` if `path` is
+/// not a template of the repository layout
+fn template_name(path: &Path) -> Option<(u8, String)> {
+    let components: Vec<&str> = path
+        .components()
+        .map(|c| match c {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    let (file, dirs) = components.split_last()?;
+    let rank = match dirs.first() {
+        None => 0,
+        Some(&"Global") => 1,
+        Some(&"community") => 2,
+        Some(_) => return None,
+    };
+
+    let stem = file.strip_suffix(EXTENSION)?.trim();
+    if stem.is_empty() {
+        return None;
+    }
+    let name = stem.to_lowercase().replace(' ', "-");
+    let name = RENAMES
+        .iter()
+        .find(|(upstream, _)| *upstream == name)
+        .map_or(name, |(_, renamed)| renamed.to_string());
+    Some((rank, name))
+}
+
+/// Reads the templates of a local checkout of the repository
+pub fn read_checkout(root: &Path) -> Result<BTreeMap<String, String>> {
+    let mut collector = Collector::default();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries: Vec<PathBuf> = fs::read_dir(&dir)
+            .with_context(|| format!("reading {}", dir.display()))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("reading {}", dir.display()))?;
+        entries.sort();
+        for path in entries {
+            let Ok(relative) = path.strip_prefix(root) else {
+                continue;
+            };
+            if path.is_dir() {
+                if !relative.starts_with(".git") {
+                    pending.push(path);
+                }
+            } else if template_name(relative).is_some() {
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("reading {}", path.display()))?;
+                collector.add(relative, content);
+            }
+        }
+    }
+    Ok(collector.finish())
+}
+
+/// Reads the templates of a gzipped tarball of the repository, as produced by
+/// GitHub's archive download (everything below a single top-level directory)
+pub fn read_tarball(reader: impl Read) -> Result<BTreeMap<String, String>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    let mut collector = Collector::default();
+    for entry in archive.entries().context("reading archive")? {
+        let mut entry = entry.context("reading archive entry")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().context("reading archive entry path")?;
+        let relative: PathBuf = path.components().skip(1).collect();
+        if template_name(&relative).is_none() {
+            continue;
+        }
+        let mut content = String::new();
+        if let Err(e) = entry.read_to_string(&mut content) {
+            debug!("Skipping {}: {e}", relative.display());
+            continue;
+        }
+        collector.add(&relative, content);
+    }
+    Ok(collector.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    const FILES: &[(&str, &str)] = &[
+        ("Python.gitignore", "__pycache__/\n"),
+        ("C++.gitignore", "*.o\n"),
+        ("Global/macOS.gitignore", ".DS_Store\n"),
+        ("Global/Python.gitignore", "shadowed\n"),
+        ("Global/VisualStudioCode.gitignore", ".vscode/*\n"),
+        ("community/JavaScript/Vue.gitignore", ".nuxt\n"),
+        (".github/workflow.gitignore", "ignored\n"),
+        ("README.md", "# gitignore\n"),
+    ];
+
+    fn expected() -> BTreeMap<String, String> {
+        [
+            ("cpp", "*.o\n"),
+            ("macos", ".DS_Store\n"),
+            ("python", "__pycache__/\n"),
+            ("vscode", ".vscode/*\n"),
+            ("vue", ".nuxt\n"),
+        ]
+        .into_iter()
+        .map(|(name, content)| (name.to_string(), content.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn maps_file_names_to_template_names() {
+        let name = |path: &str| template_name(Path::new(path));
+        assert_eq!(name("Python.gitignore"), Some((0, "python".to_string())));
+        assert_eq!(
+            name("Global/JetBrains.gitignore"),
+            Some((1, "intellij".to_string()))
+        );
+        assert_eq!(
+            name("community/Golang/Hugo.gitignore"),
+            Some((2, "hugo".to_string()))
+        );
+        assert_eq!(name("Other/Foo.gitignore"), None);
+        assert_eq!(name("LICENSE"), None);
+        assert_eq!(name(".gitignore"), None);
+    }
+
+    #[test]
+    fn reads_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in FILES {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        assert_eq!(read_checkout(dir.path()).unwrap(), expected());
+        assert_eq!(
+            Location::parse(dir.path().to_str().unwrap()),
+            Location::Checkout(dir.path().to_path_buf())
+        );
+    }
+
+    #[test]
+    fn reads_tarball() {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        // Community templates come first in the archive but still lose
+        for (path, content) in FILES.iter().rev() {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(
+                    &mut header,
+                    format!("gitignore-main/{path}"),
+                    content.as_bytes(),
+                )
+                .unwrap();
+        }
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+        assert_eq!(read_tarball(archive.as_slice()).unwrap(), expected());
+    }
+}
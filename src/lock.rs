@@ -0,0 +1,154 @@
+//! The `ignr.lock` lockfile.
+//!
+//! `generate` records the source and content hash of every template it used
+//! next to the `.gitignore`. When the lockfile is present, later runs pick the
+//! copy of each template with the recorded hash so that every machine produces
+//! the same output, regardless of which templates it has synced locally.
+//!
+//! Entries are keyed by template name and source: a template that includes
+//! its own name records both its copy and the shadowed copy it extends.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::templates::ResolvedTemplate;
+
+/// File name of the lockfile, stored next to `.gitignore`
+pub const LOCK_FILE: &str = "ignr.lock";
+
+const LOCK_VERSION: u32 = 1;
+
+const LOCK_HEADER: &str = "\
+# This file is generated by ignr. Commit it to pin template versions.
+# Run `ignr generate --update-lock` to pick up template changes.
+";
+
+/// A template version recorded in the lockfile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedTemplate {
+    pub name: String,
+    /// Name of the template source
+    pub source: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub version: u32,
+    #[serde(default, rename = "template")]
+    pub templates: Vec<LockedTemplate>,
+}
+
+impl Lockfile {
+    /// Builds a lockfile from the templates used by a merge
+    pub fn from_resolved(resolved: &[ResolvedTemplate]) -> Self {
+        let mut templates: Vec<LockedTemplate> = resolved
+            .iter()
+            .map(|t| LockedTemplate {
+                name: t.name.clone(),
+                source: t.source.clone(),
+                sha256: t.hash(),
+            })
+            .collect();
+        templates.sort_by(|a, b| (&a.name, &a.source).cmp(&(&b.name, &b.source)));
+        Self {
+            version: LOCK_VERSION,
+            templates,
+        }
+    }
+
+    /// Reads the lockfile from `path` if it exists
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading lockfile {}", path.display()))?;
+        let lock: Lockfile = toml::from_str(&text)
+            .with_context(|| format!("parsing lockfile {}", path.display()))?;
+        if lock.version > LOCK_VERSION {
+            return Err(anyhow!(
+                "{} was written by a newer version of ignr (lockfile version {})",
+                path.display(),
+                lock.version
+            ));
+        }
+        Ok(Some(lock))
+    }
+
+    /// Renders the lockfile as TOML, including the explanatory header
+    pub fn render(&self) -> Result<String> {
+        let body = toml::to_string(self).context("serializing lockfile")?;
+        Ok(format!("{LOCK_HEADER}\n{body}"))
+    }
+
+    /// Writes the lockfile, leaving the file untouched if nothing changed.
+    /// Returns whether the file was written.
+    pub fn save(&self, path: &Path) -> Result<bool> {
+        let rendered = self.render()?;
+        if fs::read_to_string(path).is_ok_and(|existing| existing == rendered) {
+            return Ok(false);
+        }
+        fs::write(path, rendered)
+            .with_context(|| format!("writing lockfile {}", path.display()))?;
+        Ok(true)
+    }
+
+    /// Every recorded copy of `name`, one per source
+    pub fn entries(&self, name: &str) -> Vec<&LockedTemplate> {
+        let name = name.to_lowercase();
+        self.templates.iter().filter(|t| t.name == name).collect()
+    }
+}
+
+/// SHA-256 of a template's content, hex encoded
+pub fn content_hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(name: &str, source: &str, content: &str) -> ResolvedTemplate {
+        ResolvedTemplate {
+            name: name.to_string(),
+            source: source.to_string(),
+            path: None,
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn lockfile_round_trips() {
+        let lock = Lockfile::from_resolved(&[
+            resolved("rust", "embedded", "/target/\n"),
+            resolved("node", "synced", "node_modules/\n"),
+        ]);
+        assert_eq!(lock.templates[0].name, "node");
+
+        let rendered = lock.render().unwrap();
+        assert!(rendered.starts_with("# This file is generated by ignr."));
+        let parsed: Lockfile = toml::from_str(&rendered).unwrap();
+        assert_eq!(parsed, lock);
+        assert_eq!(parsed.entries("Rust")[0].sha256, content_hash("/target/\n"));
+        assert!(parsed.entries("python").is_empty());
+    }
+
+    #[test]
+    fn save_skips_unchanged_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCK_FILE);
+        let lock = Lockfile::from_resolved(&[resolved("rust", "embedded", "x")]);
+        assert!(lock.save(&path).unwrap());
+        assert!(!lock.save(&path).unwrap());
+        assert_eq!(Lockfile::load(&path).unwrap(), Some(lock));
+    }
+}
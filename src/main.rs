@@ -1,4 +1,4 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::fmt;
 use std::fs;
@@ -11,44 +11,26 @@ use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
 use config::{Config, Environment, File, FileFormat};
 use env_logger::fmt::WriteStyle;
-use ignore::WalkBuilder;
 use log::{LevelFilter, debug, info, warn};
 use serde::{Deserialize, Serialize};
 
-const APP_NAME: &str = env!("CARGO_PKG_NAME");
+mod detect;
+mod diff;
+mod github;
+mod lock;
+mod managed;
+mod merge;
+mod meta;
+mod project;
+mod search;
+mod sync;
+mod templates;
+
+use detect::{RuleSet, detect_technologies};
+use lock::Lockfile;
+use templates::{EMBEDDED_TEMPLATES, Merged, SourceKind, TemplateManager};
 
-// Embedded templates from the templates/ directory
-const EMBEDDED_TEMPLATES: &[(&str, &str)] = &[
-    ("rust", include_str!("../templates/rust.gitignore")),
-    ("python", include_str!("../templates/python.gitignore")),
-    ("node", include_str!("../templates/node.gitignore")),
-    ("go", include_str!("../templates/go.gitignore")),
-    ("java", include_str!("../templates/java.gitignore")),
-    ("csharp", include_str!("../templates/csharp.gitignore")),
-    ("cpp", include_str!("../templates/cpp.gitignore")),
-    ("ruby", include_str!("../templates/ruby.gitignore")),
-    ("swift", include_str!("../templates/swift.gitignore")),
-    ("kotlin", include_str!("../templates/kotlin.gitignore")),
-    ("php", include_str!("../templates/php.gitignore")),
-    ("scala", include_str!("../templates/scala.gitignore")),
-    ("elixir", include_str!("../templates/elixir.gitignore")),
-    ("haskell", include_str!("../templates/haskell.gitignore")),
-    ("zig", include_str!("../templates/zig.gitignore")),
-    ("dart", include_str!("../templates/dart.gitignore")),
-    (
-        "terraform",
-        include_str!("../templates/terraform.gitignore"),
-    ),
-    ("ansible", include_str!("../templates/ansible.gitignore")),
-    ("docker", include_str!("../templates/docker.gitignore")),
-    ("vscode", include_str!("../templates/vscode.gitignore")),
-    ("intellij", include_str!("../templates/intellij.gitignore")),
-    ("vim", include_str!("../templates/vim.gitignore")),
-    ("emacs", include_str!("../templates/emacs.gitignore")),
-    ("linux", include_str!("../templates/linux.gitignore")),
-    ("macos", include_str!("../templates/macos.gitignore")),
-    ("windows", include_str!("../templates/windows.gitignore")),
-];
+const APP_NAME: &str = env!("CARGO_PKG_NAME");
 
 fn main() {
     if let Err(err) = try_main() {
@@ -60,16 +42,27 @@ fn main() {
 fn try_main() -> Result<()> {
     let cli = Cli::parse();
 
-    let ctx = RuntimeContext::new(cli.common.clone())?;
+    let ctx = RuntimeContext::new(cli.common.clone(), &cli.command.project_dir())?;
     ctx.init_logging()?;
     debug!("resolved paths: {:#?}", ctx.paths);
 
-    match cli.command {
-        Command::Generate(cmd) => handle_generate(&ctx, cmd),
-        Command::Sync(cmd) => handle_sync(&ctx, cmd),
-        Command::List => handle_list(&ctx),
-        Command::Init(cmd) => handle_init(&ctx, cmd),
-        Command::Config { command } => handle_config(&ctx, command),
+    run(&ctx, cli.command)
+}
+
+fn run(ctx: &RuntimeContext, command: Command) -> Result<()> {
+    match command {
+        Command::Generate(cmd) => handle_generate(ctx, cmd),
+        Command::Check(cmd) => handle_check(ctx, cmd),
+        Command::Diff(cmd) => handle_diff(ctx, cmd),
+        Command::Detect(cmd) => handle_detect(ctx, cmd),
+        Command::Add(cmd) => handle_edit(ctx, cmd, EditOp::Add),
+        Command::Remove(cmd) => handle_edit(ctx, cmd, EditOp::Remove),
+        Command::Sync(cmd) => handle_sync(ctx, cmd),
+        Command::List(cmd) => handle_list(ctx, cmd),
+        Command::Show(cmd) => handle_show(ctx, cmd),
+        Command::Search(cmd) => handle_search(ctx, cmd),
+        Command::Init(cmd) => handle_init(ctx, cmd),
+        Command::Config { command } => handle_config(ctx, command),
         Command::Completions { shell } => handle_completions(shell),
     }
 }
@@ -137,11 +130,26 @@ enum Command {
     /// Generate .gitignore (default behavior, auto-detects stack)
     #[command(alias = "gen", alias = "g")]
     Generate(GenerateCommand),
+    /// Check whether the managed section of .gitignore is up to date
+    Check(CheckCommand),
+    /// Preview the changes generate would make to .gitignore
+    Diff(DiffCommand),
+    /// Show detected technologies and why they were picked
+    Detect(DetectCommand),
+    /// Add templates to the managed section of .gitignore
+    Add(EditCommand),
+    /// Remove templates from the managed section of .gitignore
+    #[command(alias = "rm")]
+    Remove(EditCommand),
     /// Sync templates from remote source
     Sync(SyncCommand),
     /// List available templates
     #[command(alias = "ls")]
-    List,
+    List(ListCommand),
+    /// Print a template and the source it is loaded from
+    Show(ShowCommand),
+    /// Search template names, aliases and patterns
+    Search(SearchCommand),
     /// Create config directories and default files
     Init(InitCommand),
     /// Inspect and manage configuration
@@ -156,6 +164,21 @@ enum Command {
     },
 }
 
+impl Command {
+    /// Directory used to discover the project config
+    fn project_dir(&self) -> PathBuf {
+        let dir = match self {
+            Command::Generate(cmd) => cmd.selection.dir.as_deref(),
+            Command::Check(cmd) => cmd.selection.dir.as_deref(),
+            Command::Diff(cmd) => cmd.selection.dir.as_deref(),
+            Command::Detect(cmd) => cmd.dir.as_deref(),
+            Command::Add(cmd) | Command::Remove(cmd) => cmd.dir.as_deref(),
+            _ => None,
+        };
+        resolve_scan_dir(dir)
+    }
+}
+
 #[derive(Debug, Clone, Args)]
 struct GenerateCommand {
     /// Print to stdout instead of writing to .gitignore
@@ -164,28 +187,185 @@ struct GenerateCommand {
     /// Force append (add duplicate managed section instead of updating existing one)
     #[arg(long, short = 'a')]
     append: bool,
+    #[command(flatten)]
+    selection: SelectionArgs,
+    /// Create .gitignore even if not in a git repo, and overwrite hand edits
+    /// made inside the managed section
+    #[arg(long, short = 'f')]
+    force: bool,
+    /// Leave the date out of the section header (see header.date)
+    #[arg(long)]
+    no_date: bool,
+}
+
+/// Options deciding which templates end up in the managed section
+#[derive(Debug, Clone, Args)]
+struct SelectionArgs {
     /// Skip auto-detection, only use explicitly specified templates
     #[arg(long)]
     no_detect: bool,
+    /// Re-use the templates, exclusions and patterns recorded in the header of
+    /// the existing ignr section instead of detecting
+    #[arg(long, conflicts_with = "no_detect")]
+    from_header: bool,
     /// Additional templates to include
     #[arg(long, short = 't', value_name = "TEMPLATE")]
     add: Vec<String>,
+    /// Detected templates to leave out (in addition to detection.exclude)
+    #[arg(long, short = 'x', value_name = "TEMPLATE")]
+    exclude: Vec<String>,
+    /// Extra pattern for the custom section (in addition to templates.extra_patterns)
+    #[arg(long = "pattern", value_name = "PATTERN")]
+    patterns: Vec<String>,
+    /// Directory to scan (defaults to current directory)
+    #[arg(long, short = 'd', value_name = "PATH")]
+    dir: Option<PathBuf>,
+    /// Maximum directory depth to scan
+    #[arg(long, default_value = "10")]
+    depth: usize,
+    #[command(flatten)]
+    lock: LockArgs,
+    /// Fail if a requested template does not exist instead of warning
+    #[arg(long)]
+    strict: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+struct LockArgs {
+    /// Fail if a template version pinned in ignr.lock is unavailable
+    #[arg(long, conflicts_with = "update_lock")]
+    locked: bool,
+    /// Ignore ignr.lock and use the current template versions
+    #[arg(long)]
+    update_lock: bool,
+}
+
+impl SelectionArgs {
+    fn resolve_dir(&self) -> PathBuf {
+        resolve_scan_dir(self.dir.as_deref())
+    }
+}
+
+fn resolve_scan_dir(dir: Option<&Path>) -> PathBuf {
+    let dir = dir.map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+    dir.canonicalize().unwrap_or(dir)
+}
+
+#[derive(Debug, Clone, Args)]
+struct DetectCommand {
+    /// Show which files and rules triggered each template
+    #[arg(long)]
+    explain: bool,
     /// Directory to scan (defaults to current directory)
     #[arg(long, short = 'd', value_name = "PATH")]
     dir: Option<PathBuf>,
     /// Maximum directory depth to scan
     #[arg(long, default_value = "10")]
     depth: usize,
-    /// Create .gitignore even if not in a git repo
+}
+
+#[derive(Debug, Clone, Args)]
+struct CheckCommand {
+    #[command(flatten)]
+    selection: SelectionArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+struct DiffCommand {
+    /// Preview appending a new managed section instead of updating the existing one
+    #[arg(long, short = 'a')]
+    append: bool,
+    #[command(flatten)]
+    selection: SelectionArgs,
+    /// Preview overwriting hand edits made inside the managed section
+    #[arg(long, short = 'f')]
+    force: bool,
+    /// Leave the date out of the section header (see header.date)
+    #[arg(long)]
+    no_date: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+struct EditCommand {
+    /// Templates (or aliases) to add or remove
+    #[arg(value_name = "TEMPLATE", required = true)]
+    templates: Vec<String>,
+    /// Directory containing the .gitignore (defaults to current directory)
+    #[arg(long, short = 'd', value_name = "PATH")]
+    dir: Option<PathBuf>,
+    /// Overwrite hand edits made inside the managed section
     #[arg(long, short = 'f')]
     force: bool,
+    /// Leave the date out of the section header (see header.date)
+    #[arg(long)]
+    no_date: bool,
+    #[command(flatten)]
+    lock: LockArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditOp {
+    Add,
+    Remove,
 }
 
 #[derive(Debug, Clone, Args)]
 struct SyncCommand {
+    /// Only sync the template source with this name
+    #[arg(long, value_name = "NAME")]
+    source: Option<String>,
     /// Override the remote URL to sync from
     #[arg(long, value_name = "URL")]
     url: Option<String>,
+    /// Sync from a github/gitignore-style repository instead: a tarball URL,
+    /// a local .tar.gz or a checkout (defaults to the upstream repository)
+    #[arg(
+        long,
+        value_name = "URL|PATH",
+        num_args = 0..=1,
+        default_missing_value = github::DEFAULT_TARBALL,
+        conflicts_with_all = ["url", "restart", "allow_failures"]
+    )]
+    github: Option<String>,
+    /// Number of templates to download concurrently
+    #[arg(long, short = 'j', default_value = "8", value_parser = clap::value_parser!(u16).range(1..=64))]
+    jobs: u16,
+    /// Start over instead of resuming an unfinished sync
+    #[arg(long)]
+    restart: bool,
+    /// Install the synced templates even if some could not be fetched
+    #[arg(long)]
+    allow_failures: bool,
+    /// Restore the templates replaced by the last sync
+    #[arg(long, conflicts_with_all = ["url", "github", "jobs", "restart", "allow_failures"])]
+    rollback: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+struct ListCommand {
+    /// Show the effective detection rules instead of templates
+    #[arg(long)]
+    rules: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+struct ShowCommand {
+    /// Template name or alias
+    #[arg(value_name = "TEMPLATE")]
+    template: String,
+    /// Also show the copies shadowed by lower priority sources and how they differ
+    #[arg(long)]
+    all_sources: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+struct SearchCommand {
+    /// Terms to look for, e.g. a template name or a pattern like `*.pyc`
+    #[arg(value_name = "TERM", required = true)]
+    terms: Vec<String>,
+    /// Maximum number of results to show
+    #[arg(long, short = 'n', default_value = "20")]
+    limit: usize,
 }
 
 #[derive(Debug, Clone, Args)]
@@ -193,6 +373,9 @@ struct InitCommand {
     /// Recreate configuration even if it already exists
     #[arg(long = "force")]
     force: bool,
+    /// Create a project config (.ignr.toml) in the current directory instead
+    #[arg(long)]
+    project: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -213,8 +396,9 @@ struct RuntimeContext {
 }
 
 impl RuntimeContext {
-    fn new(common: CommonOpts) -> Result<Self> {
+    fn new(common: CommonOpts, project_dir: &Path) -> Result<Self> {
         let mut paths = AppPaths::discover(common.config.clone())?;
+        paths.project_config = project::find_project_config(project_dir);
         let config = load_or_init_config(&mut paths, &common)?;
         let paths = paths.apply_overrides(&config)?;
         let ctx = Self {
@@ -223,10 +407,34 @@ impl RuntimeContext {
             config,
         };
         ctx.ensure_directories()?;
+        ctx.recover_interrupted_syncs();
         ctx.ensure_embedded_templates()?;
         Ok(ctx)
     }
 
+    /// Finishes syncs interrupted mid-swap, which leave a source without its
+    /// templates directory. Runs before the embedded templates are written so
+    /// they do not take the place of the synced set.
+    ///
+    /// Failures are only logged so they cannot break unrelated commands.
+    fn recover_interrupted_syncs(&self) {
+        if self.common.dry_run {
+            return;
+        }
+        for source in templates::sources(&self.config) {
+            if !source.kind.is_synced() {
+                continue;
+            }
+            let layout = source.sync_layout(&self.paths.data_dir, &self.paths.cache_dir);
+            if let Err(err) = layout.recover() {
+                warn!(
+                    "Could not recover the interrupted sync of template source '{}': {err:#}",
+                    source.name
+                );
+            }
+        }
+    }
+
     fn ensure_embedded_templates(&self) -> Result<()> {
         let templates_dir = self.paths.data_dir.join("templates");
 
@@ -278,16 +486,9 @@ impl RuntimeContext {
 
         builder.filter_level(self.effective_log_level());
 
-        let force_color = matches!(self.common.color, ColorOption::Always)
-            || env::var_os("FORCE_COLOR").is_some();
-        let disable_color = self.common.no_color
-            || matches!(self.common.color, ColorOption::Never)
-            || env::var_os("NO_COLOR").is_some()
-            || (!force_color && !io::stderr().is_terminal());
-
-        if disable_color {
+        if !self.color_enabled(io::stderr().is_terminal()) {
             builder.write_style(WriteStyle::Never);
-        } else if force_color {
+        } else if self.force_color() {
             builder.write_style(WriteStyle::Always);
         } else {
             builder.write_style(WriteStyle::Auto);
@@ -301,6 +502,19 @@ impl RuntimeContext {
         })
     }
 
+    fn force_color(&self) -> bool {
+        matches!(self.common.color, ColorOption::Always) || env::var_os("FORCE_COLOR").is_some()
+    }
+
+    /// Whether ANSI colors should be used on a stream
+    fn color_enabled(&self, is_terminal: bool) -> bool {
+        let disable_color = self.common.no_color
+            || matches!(self.common.color, ColorOption::Never)
+            || env::var_os("NO_COLOR").is_some()
+            || (!self.force_color() && !is_terminal);
+        !disable_color
+    }
+
     fn effective_log_level(&self) -> LevelFilter {
         if self.common.trace {
             LevelFilter::Trace
@@ -342,6 +556,8 @@ impl RuntimeContext {
 #[derive(Debug, Clone)]
 struct AppPaths {
     config_file: PathBuf,
+    /// Project-local config layered on top of `config_file`
+    project_config: Option<PathBuf>,
     data_dir: PathBuf,
     cache_dir: PathBuf,
 }
@@ -369,6 +585,7 @@ impl AppPaths {
 
         Ok(Self {
             config_file,
+            project_config: None,
             data_dir,
             cache_dir,
         })
@@ -385,24 +602,15 @@ impl AppPaths {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(default)]
 struct AppConfig {
     templates: TemplatesConfig,
     detection: DetectionConfig,
+    header: HeaderConfig,
     paths: PathsConfig,
 }
 
-impl Default for AppConfig {
-    fn default() -> Self {
-        Self {
-            templates: TemplatesConfig::default(),
-            detection: DetectionConfig::default(),
-            paths: PathsConfig::default(),
-        }
-    }
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 struct TemplatesConfig {
@@ -414,6 +622,29 @@ struct TemplatesConfig {
     prefer_local: bool,
     /// Additional templates to always include
     always_include: Vec<String>,
+    /// Extra names for templates, e.g. `ts = "node"`
+    aliases: BTreeMap<String, String>,
+    /// Individual patterns added to a `custom` section of the managed block
+    extra_patterns: Vec<String>,
+    /// Named template sources; replace `template_dir` and `template_url` when set
+    sources: Vec<SourceConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SourceConfig {
+    /// Shown by `list` and `show` and recorded in ignr.lock
+    name: String,
+    kind: SourceKind,
+    /// Directory for `dir`, API URL for `http-api`, archive URL, file or
+    /// checkout for `git-tarball` (defaults to github/gitignore)
+    #[serde(default)]
+    location: Option<String>,
+    /// Sources with a higher priority are consulted first
+    #[serde(default)]
+    priority: i64,
+    /// Prefix addressing this source directly, as in `corp/python`
+    #[serde(default)]
+    namespace: Option<String>,
 }
 
 impl Default for TemplatesConfig {
@@ -423,6 +654,9 @@ impl Default for TemplatesConfig {
             template_url: Some("https://www.toptal.com/developers/gitignore/api".to_string()),
             prefer_local: true,
             always_include: vec![],
+            aliases: BTreeMap::new(),
+            extra_patterns: vec![],
+            sources: vec![],
         }
     }
 }
@@ -436,6 +670,16 @@ struct DetectionConfig {
     detect_os: bool,
     /// Whether to detect IDE/editor patterns
     detect_ide: bool,
+    /// Whether to load the built-in detection rules
+    builtin_rules: bool,
+    /// Additional TOML rule files, applied after the built-in rules
+    rule_files: Vec<String>,
+    /// Minimum score a template needs to be detected
+    min_score: f64,
+    /// Directory names whose contents count less towards detection
+    vendor_dirs: Vec<String>,
+    /// Templates never added by detection
+    exclude: Vec<String>,
 }
 
 impl Default for DetectionConfig {
@@ -444,10 +688,41 @@ impl Default for DetectionConfig {
             max_depth: 10,
             detect_os: true,
             detect_ide: true,
+            builtin_rules: true,
+            rule_files: vec![],
+            min_score: 1.0,
+            vendor_dirs: [
+                "vendor",
+                "third_party",
+                "thirdparty",
+                "external",
+                "node_modules",
+                "fixtures",
+                "testdata",
+                "test-fixtures",
+                "__fixtures__",
+            ]
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+            exclude: vec![],
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct HeaderConfig {
+    /// Whether to record the generation date in the managed section header
+    date: bool,
+}
+
+impl Default for HeaderConfig {
+    fn default() -> Self {
+        Self { date: true }
+    }
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(default)]
 struct PathsConfig {
@@ -455,318 +730,246 @@ struct PathsConfig {
     cache_dir: Option<String>,
 }
 
-/// Detects technologies used in a directory
-fn detect_technologies(
-    dir: &Path,
-    config: &DetectionConfig,
-    depth: usize,
-) -> Result<BTreeSet<String>> {
-    let mut detected = BTreeSet::new();
-
-    let walker = WalkBuilder::new(dir)
-        .max_depth(Some(depth.min(config.max_depth)))
-        .hidden(false)
-        .git_ignore(true)
-        .build();
-
-    for entry in walker.flatten() {
-        let path = entry.path();
-        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-
-        // Detect by manifest files
-        match file_name {
-            "Cargo.toml" => {
-                detected.insert("rust".to_string());
-            }
-            "package.json" => {
-                detected.insert("node".to_string());
-            }
-            "requirements.txt" | "pyproject.toml" | "setup.py" | "Pipfile" | "uv.lock" => {
-                detected.insert("python".to_string());
-            }
-            "go.mod" | "go.sum" => {
-                detected.insert("go".to_string());
-            }
-            "pom.xml" | "build.gradle" => {
-                detected.insert("java".to_string());
-            }
-            "build.gradle.kts" => {
-                detected.insert("java".to_string());
-                // Check if this is a Kotlin project
-                if path.to_string_lossy().contains("kotlin") {
-                    detected.insert("kotlin".to_string());
-                }
-            }
-            "CMakeLists.txt" | "Makefile" | "configure.ac" => {
-                detected.insert("cpp".to_string());
-            }
-            "Gemfile" | "Rakefile" => {
-                detected.insert("ruby".to_string());
-            }
-            "Package.swift" => {
-                detected.insert("swift".to_string());
-            }
-            "composer.json" => {
-                detected.insert("php".to_string());
-            }
-            "build.sbt" => {
-                detected.insert("scala".to_string());
-            }
-            "mix.exs" => {
-                detected.insert("elixir".to_string());
-            }
-            "stack.yaml" | "cabal.project" => {
-                detected.insert("haskell".to_string());
-            }
-            "build.zig" => {
-                detected.insert("zig".to_string());
-            }
-            "pubspec.yaml" => {
-                detected.insert("dart".to_string());
-            }
-            "main.tf" | "terraform.tf" => {
-                detected.insert("terraform".to_string());
-            }
-            "playbook.yml" | "ansible.cfg" => {
-                detected.insert("ansible".to_string());
-            }
-            "Dockerfile" | "docker-compose.yml" | "docker-compose.yaml" => {
-                detected.insert("docker".to_string());
-            }
-            _ => {}
-        }
-
-        // Detect by file extension
-        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-            match ext {
-                "rs" => {
-                    detected.insert("rust".to_string());
-                }
-                "py" | "pyw" | "pyi" => {
-                    detected.insert("python".to_string());
-                }
-                "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => {
-                    detected.insert("node".to_string());
-                }
-                "go" => {
-                    detected.insert("go".to_string());
-                }
-                "java" => {
-                    detected.insert("java".to_string());
-                }
-                "cs" | "fs" | "vb" => {
-                    detected.insert("csharp".to_string());
-                }
-                "c" | "cpp" | "cc" | "cxx" | "h" | "hpp" | "hxx" => {
-                    detected.insert("cpp".to_string());
-                }
-                "rb" => {
-                    detected.insert("ruby".to_string());
-                }
-                "swift" => {
-                    detected.insert("swift".to_string());
-                }
-                "kt" | "kts" => {
-                    detected.insert("kotlin".to_string());
-                }
-                "php" => {
-                    detected.insert("php".to_string());
-                }
-                "scala" | "sc" => {
-                    detected.insert("scala".to_string());
-                }
-                "ex" | "exs" => {
-                    detected.insert("elixir".to_string());
-                }
-                "hs" | "lhs" => {
-                    detected.insert("haskell".to_string());
-                }
-                "zig" => {
-                    detected.insert("zig".to_string());
-                }
-                "dart" => {
-                    detected.insert("dart".to_string());
-                }
-                "tf" | "tfvars" => {
-                    detected.insert("terraform".to_string());
-                }
-                "csproj" | "sln" | "fsproj" => {
-                    detected.insert("csharp".to_string());
-                }
-                _ => {}
-            }
-        }
+/// Templates chosen for the managed section
+#[derive(Debug, Clone, Default)]
+struct Selection {
+    templates: Vec<String>,
+    /// Detected templates that were excluded
+    excluded: Vec<String>,
+    /// Patterns for the custom section, from config and `--pattern`
+    patterns: Vec<String>,
+    /// Templates added with `ignr add`, recorded in the header
+    added: Vec<String>,
+    /// Templates removed with `ignr remove`, recorded in the header
+    removed: Vec<String>,
+}
 
-        // Detect IDE/editor directories
-        if config.detect_ide && path.is_dir() {
-            match file_name {
-                ".vscode" => {
-                    detected.insert("vscode".to_string());
-                }
-                ".idea" => {
-                    detected.insert("intellij".to_string());
-                }
-                ".vim" | ".nvim" => {
-                    detected.insert("vim".to_string());
-                }
-                ".emacs.d" => {
-                    detected.insert("emacs".to_string());
-                }
-                _ => {}
-            }
-        }
+impl Selection {
+    fn is_empty(&self) -> bool {
+        self.templates.is_empty() && self.patterns.is_empty()
     }
 
-    // Detect OS
-    if config.detect_os {
-        #[cfg(target_os = "linux")]
-        detected.insert("linux".to_string());
-        #[cfg(target_os = "macos")]
-        detected.insert("macos".to_string());
-        #[cfg(target_os = "windows")]
-        detected.insert("windows".to_string());
+    /// The `detected`, `excluded` and `patterns` fields of generate's JSON/YAML output
+    fn report(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut report = serde_json::Map::new();
+        report.insert("detected".to_string(), self.templates.clone().into());
+        report.insert("excluded".to_string(), self.excluded.clone().into());
+        report.insert("patterns".to_string(), self.patterns.clone().into());
+        report
     }
-
-    Ok(detected)
-}
-
-/// Template manager for loading and merging templates
-struct TemplateManager<'a> {
-    config: &'a AppConfig,
-    data_dir: &'a Path,
 }
 
-impl<'a> TemplateManager<'a> {
-    fn new(config: &'a AppConfig, data_dir: &'a Path) -> Self {
-        Self { config, data_dir }
-    }
-
-    fn list_available(&self) -> Vec<String> {
-        let mut templates: BTreeSet<String> = EMBEDDED_TEMPLATES
-            .iter()
-            .map(|(name, _)| name.to_string())
-            .collect();
-
-        // Add templates from custom directory
-        if let Some(ref dir) = self.config.templates.template_dir {
-            if let Ok(expanded) = expand_str_path(dir) {
-                if let Ok(entries) = fs::read_dir(expanded) {
-                    for entry in entries.flatten() {
-                        let path = entry.path();
-                        if let Some(name) = path.file_stem().and_then(|n| n.to_str()) {
-                            if path.extension().and_then(|e| e.to_str()) == Some("gitignore") {
-                                templates.insert(name.to_string());
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        // Add templates from data directory
-        let data_templates = self.data_dir.join("templates");
-        if let Ok(entries) = fs::read_dir(&data_templates) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if let Some(name) = path.file_stem().and_then(|n| n.to_str()) {
-                    if path.extension().and_then(|e| e.to_str()) == Some("gitignore") {
-                        templates.insert(name.to_string());
-                    }
-                }
-            }
+/// Resolves the template set from detection, explicit additions and config.
+///
+/// Exclusions apply to detected templates only; `always_include` templates and
+/// templates requested explicitly with `--add` are always kept. The templates added and
+/// removed with `ignr add` / `ignr remove` (recorded in the existing header)
+/// are applied on top.
+fn select_templates(
+    ctx: &RuntimeContext,
+    selection: &SelectionArgs,
+    dir: &Path,
+) -> Result<Selection> {
+    let gitignore_path = dir.join(".gitignore");
+    let previous = match previous_header(&gitignore_path) {
+        Ok(previous) => previous,
+        // generate refuses to overwrite the section anyway unless --force is set
+        Err(err) if !selection.from_header => {
+            warn!("Ignoring the templates recorded in the ignr section: {err:#}");
+            None
         }
+        Err(err) => return Err(err),
+    };
+    let recorded = if selection.from_header {
+        let header = previous
+            .clone()
+            .ok_or_else(|| anyhow!("{} has no ignr section to re-run", gitignore_path.display()))?;
+        Some(header)
+    } else {
+        None
+    };
+    let (added, removed) = previous
+        .map(|header| (header.added, header.removed))
+        .unwrap_or_default();
 
-        templates.into_iter().collect()
-    }
-
-    fn get_template(&self, name: &str) -> Option<String> {
-        let name_lower = name.to_lowercase();
-
-        // Check custom template directory first if prefer_local is true
-        if self.config.templates.prefer_local {
-            if let Some(content) = self.load_from_custom_dir(&name_lower) {
-                return Some(content);
-            }
+    // Detect technologies
+    let mut templates: BTreeSet<String> = if let Some(ref header) = recorded {
+        header.templates.iter().cloned().collect()
+    } else if selection.no_detect {
+        BTreeSet::new()
+    } else {
+        let rules = load_rules(ctx)?;
+        let detection = detect_technologies(dir, &ctx.config.detection, &rules, selection.depth)?;
+        for candidate in detection.rejected() {
+            info!(
+                "Skipping '{}': score {:.2} below min_score {}",
+                candidate.template, candidate.score, ctx.config.detection.min_score
+            );
         }
+        detection.names()
+    };
 
-        // Check data directory (synced templates)
-        if let Some(content) = self.load_from_data_dir(&name_lower) {
-            return Some(content);
+    // Resolve aliases in requested names, reporting unknown templates
+    let index = TemplateManager::new(&ctx.config, &ctx.paths.data_dir).name_index();
+    let mut missing = Vec::new();
+    let mut canonical = |name: &String| {
+        let found = index.canonical(name);
+        if found.is_none() {
+            missing.push(index.not_found_message(name));
         }
-
-        // Check embedded templates
-        for (embedded_name, content) in EMBEDDED_TEMPLATES {
-            if embedded_name.to_lowercase() == name_lower {
-                return Some(content.to_string());
-            }
+        found
+    };
+    let always_include: Vec<String> = ctx
+        .config
+        .templates
+        .always_include
+        .iter()
+        .filter_map(&mut canonical)
+        .collect();
+    let exclude: BTreeSet<String> = ctx
+        .config
+        .detection
+        .exclude
+        .iter()
+        .chain(recorded.iter().flat_map(|h| &h.excluded))
+        .chain(&selection.exclude)
+        .filter_map(&mut canonical)
+        .collect();
+    let add: Vec<String> = selection.add.iter().filter_map(&mut canonical).collect();
+
+    if !missing.is_empty() {
+        if selection.strict {
+            return Err(anyhow!("{}", missing.join("\n")));
         }
-
-        // Check custom directory if not checked yet
-        if !self.config.templates.prefer_local {
-            if let Some(content) = self.load_from_custom_dir(&name_lower) {
-                return Some(content);
-            }
+        for message in &missing {
+            warn!("{message}");
         }
-
-        None
     }
 
-    fn load_from_custom_dir(&self, name: &str) -> Option<String> {
-        let dir = self.config.templates.template_dir.as_ref()?;
-        let expanded = expand_str_path(dir).ok()?;
-        let path = expanded.join(format!("{name}.gitignore"));
-        fs::read_to_string(path).ok()
+    // Drop excluded templates
+    let mut excluded: BTreeSet<String> = templates.intersection(&exclude).cloned().collect();
+    for t in &excluded {
+        debug!("Excluding template '{t}'");
+        templates.remove(t);
+    }
+    if let Some(ref header) = recorded {
+        excluded.extend(header.excluded.iter().cloned());
     }
 
-    fn load_from_data_dir(&self, name: &str) -> Option<String> {
-        let path = self
-            .data_dir
-            .join("templates")
-            .join(format!("{name}.gitignore"));
-        fs::read_to_string(path).ok()
+    // Add always_include templates from config
+    templates.extend(always_include);
+
+    // Apply the edits made with `ignr add` and `ignr remove`
+    for t in &removed {
+        templates.remove(t);
     }
+    templates.extend(added.iter().cloned());
 
-    fn merge_templates(&self, templates: &[String]) -> String {
-        let mut lines: BTreeSet<String> = BTreeSet::new();
-        let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+    // Add explicit templates
+    templates.extend(add);
+
+    let patterns: Vec<String> = recorded
+        .iter()
+        .flat_map(|h| &h.patterns)
+        .chain(&selection.patterns)
+        .cloned()
+        .collect();
+
+    Ok(Selection {
+        templates: templates.into_iter().collect(),
+        excluded: excluded.into_iter().collect(),
+        patterns: custom_patterns(ctx, &patterns),
+        added,
+        removed,
+    })
+}
 
-        for template_name in templates {
-            if let Some(content) = self.get_template(template_name) {
-                let mut section_lines = Vec::new();
-                for line in content.lines() {
-                    let trimmed = line.trim();
-                    if !trimmed.is_empty() && !lines.contains(trimmed) {
-                        lines.insert(trimmed.to_string());
-                        section_lines.push(line.to_string());
-                    }
-                }
-                if !section_lines.is_empty() {
-                    sections.push((template_name.clone(), section_lines));
-                }
-            } else {
-                warn!("Template '{}' not found", template_name);
-            }
-        }
+/// The header of the ignr section in `gitignore_path`, if there is one
+fn previous_header(gitignore_path: &Path) -> Result<Option<managed::Header>> {
+    let existing = read_existing_gitignore(gitignore_path)?.unwrap_or_default();
+    managed::find(&existing).map(|s| s.header()).transpose()
+}
 
-        let mut output = String::new();
-        for (name, section_lines) in sections {
-            if !output.is_empty() {
-                output.push('\n');
+/// Renders the managed section for `selection`, recording its inputs in the header.
+///
+/// If the existing section in `dir` was generated from the same inputs and has
+/// the same content, its date is kept so that regenerating leaves the file
+/// untouched.
+fn render_section(
+    ctx: &RuntimeContext,
+    dir: &Path,
+    selection: &Selection,
+    merged: &Merged,
+    no_date: bool,
+    force: bool,
+) -> Result<String> {
+    let mut header = managed::Header::new(
+        &selection.templates,
+        &selection.excluded,
+        &selection.patterns,
+    );
+    header.added = selection.added.clone();
+    header.removed = selection.removed.clone();
+    let lock = Lockfile::from_resolved(&merged.resolved).render()?;
+    header.lock = Some(managed::content_hash(&lock));
+
+    if ctx.config.header.date && !no_date {
+        let gitignore_path = dir.join(".gitignore");
+        let existing = read_existing_gitignore(&gitignore_path)?.unwrap_or_default();
+        // An unreadable header is reported unless --force is about to replace it
+        let previous = match managed::find(&existing).map(|s| s.header()).transpose() {
+            Ok(previous) => previous,
+            Err(_) if force => None,
+            Err(err) => {
+                return Err(err.context(format!(
+                    "reading the ignr section header of {} (--force overwrites it)",
+                    gitignore_path.display()
+                )));
             }
-            output.push_str(&format!("# === {} ===\n", name));
-            for line in section_lines {
-                output.push_str(&line);
-                output.push('\n');
+        };
+        header.date = match previous {
+            Some(previous)
+                if previous.date.is_some() && previous.describes(&header, &merged.content) =>
+            {
+                previous.date
             }
-        }
+            _ => Some(Utc::now().format("%Y-%m-%d").to_string()),
+        };
+    }
+    Ok(managed::render(&header, &merged.content))
+}
 
-        output
+/// `templates.extra_patterns` followed by `extra`, without blanks and duplicates
+fn custom_patterns(ctx: &RuntimeContext, extra: &[String]) -> Vec<String> {
+    let mut patterns: Vec<String> = Vec::new();
+    for pattern in ctx.config.templates.extra_patterns.iter().chain(extra) {
+        let pattern = pattern.trim();
+        if !pattern.is_empty() && !patterns.iter().any(|p| p == pattern) {
+            patterns.push(pattern.to_string());
+        }
     }
+    patterns
+}
+
+/// Merges templates, pinning versions to ignr.lock unless --update-lock is set
+fn merge_selected(
+    ctx: &RuntimeContext,
+    args: &LockArgs,
+    dir: &Path,
+    selection: &Selection,
+) -> Result<Merged> {
+    let lock = if args.update_lock {
+        None
+    } else {
+        Lockfile::load(&dir.join(lock::LOCK_FILE))?
+    };
+    TemplateManager::new(&ctx.config, &ctx.paths.data_dir)
+        .with_lock(lock.as_ref(), args.locked)
+        .merge_templates(&selection.templates, &selection.patterns)
 }
 
 fn handle_generate(ctx: &RuntimeContext, cmd: GenerateCommand) -> Result<()> {
-    let dir = cmd.dir.clone().unwrap_or_else(|| PathBuf::from("."));
-    let dir = dir.canonicalize().unwrap_or(dir);
+    let dir = cmd.selection.resolve_dir();
 
     // Check if in a git repo (unless --force)
     if !cmd.force && !dir.join(".git").exists() {
@@ -787,67 +990,35 @@ fn handle_generate(ctx: &RuntimeContext, cmd: GenerateCommand) -> Result<()> {
         }
     }
 
-    // Detect technologies
-    let mut templates: BTreeSet<String> = if cmd.no_detect {
-        BTreeSet::new()
-    } else {
-        detect_technologies(&dir, &ctx.config.detection, cmd.depth)?
-    };
-
-    // Add explicit templates
-    for t in &cmd.add {
-        templates.insert(t.to_lowercase());
-    }
-
-    // Add always_include templates from config
-    for t in &ctx.config.templates.always_include {
-        templates.insert(t.to_lowercase());
-    }
+    let selection = select_templates(ctx, &cmd.selection, &dir)?;
+    let template_list = &selection.templates;
 
-    if templates.is_empty() {
-        if ctx.common.json {
-            println!(
-                "{}",
-                serde_json::json!({
-                    "detected": [],
-                    "message": "No technologies detected and none specified"
-                })
-            );
-        } else if ctx.common.yaml {
-            println!("detected: []\nmessage: No technologies detected and none specified");
-        } else {
-            println!(
-                "No technologies detected and none specified. Use --add to specify templates."
-            );
-        }
+    if selection.is_empty() {
+        report_no_templates(ctx);
         return Ok(());
     }
 
-    let template_list: Vec<String> = templates.into_iter().collect();
-    let manager = TemplateManager::new(&ctx.config, &ctx.paths.data_dir);
-    let content = manager.merge_templates(&template_list);
-
-    // Generate header
-    let date = Utc::now().format("%Y-%m-%d");
-    let detected_str = template_list.join(",");
-    let header = format!("# ---- ignr (detected: {}) @ {} ----\n", detected_str, date);
-
-    let full_content = format!("{header}\n{content}");
+    let merged = merge_selected(ctx, &cmd.selection.lock, &dir, &selection)?;
+    let full_content = render_section(ctx, &dir, &selection, &merged, cmd.no_date, cmd.force)?;
 
     if cmd.print {
         if ctx.common.json {
-            println!(
-                "{}",
-                serde_json::json!({
-                    "detected": template_list,
-                    "content": full_content
-                })
-            );
+            let mut report = selection.report();
+            report.insert("content".to_string(), full_content.into());
+            println!("{}", serde_json::Value::Object(report));
         } else if ctx.common.yaml {
             println!("detected:");
-            for t in &template_list {
+            for t in template_list {
                 println!("  - {t}");
             }
+            println!("excluded:");
+            for t in &selection.excluded {
+                println!("  - {t}");
+            }
+            println!("patterns:");
+            for p in &selection.patterns {
+                println!("  - {}", serde_json::to_string(p)?);
+            }
             println!("content: |");
             for line in full_content.lines() {
                 println!("  {line}");
@@ -872,152 +1043,849 @@ fn handle_generate(ctx: &RuntimeContext, cmd: GenerateCommand) -> Result<()> {
         return Ok(());
     }
 
-    // Handle writing to .gitignore
-    // Default behavior: preserve existing content and append/update managed section
-    let final_content = if gitignore_path.exists() {
-        let existing =
-            fs::read_to_string(&gitignore_path).context("reading existing .gitignore")?;
-
-        if cmd.append {
-            // Pure append mode: always add to end, even if managed section exists
-            format!("{existing}\n{full_content}")
-        } else if let Some(start) = existing.find("# ---- ignr (detected:") {
-            // Replace existing managed section only
-            let before = &existing[..start];
-            // The managed section extends to EOF since we don't have an end marker
-            // Future improvement: add end marker like "# ---- /ignr ----"
-            let before_trimmed = before.trim_end();
-            if before_trimmed.is_empty() {
-                full_content
-            } else {
-                format!("{before_trimmed}\n\n{full_content}")
+    let written = write_managed_section(&dir, full_content, cmd.append, cmd.force, &merged)?;
+
+    if ctx.common.json || ctx.common.yaml {
+        let mut report = selection.report();
+        report.insert(
+            "path".to_string(),
+            gitignore_path.display().to_string().into(),
+        );
+        report.insert(
+            "lockfile".to_string(),
+            written.lock_path.display().to_string().into(),
+        );
+        report.insert("changed".to_string(), written.changed.into());
+        if ctx.common.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!("{}", serde_yaml::to_string(&report)?);
+        }
+    } else if !ctx.common.quiet && !written.changed {
+        println!("{} is up to date", gitignore_path.display());
+    } else if !ctx.common.quiet {
+        println!("Generated .gitignore with: {}", template_list.join(", "));
+        if !selection.excluded.is_empty() {
+            println!("Excluded: {}", selection.excluded.join(", "));
+        }
+        if !selection.patterns.is_empty() {
+            println!("Custom patterns: {}", selection.patterns.join(" "));
+        }
+    }
+
+    Ok(())
+}
+
+/// Result of writing the managed section
+struct Written {
+    lock_path: PathBuf,
+    /// Whether .gitignore was rewritten (false if it was already up to date)
+    changed: bool,
+}
+
+/// Writes a rendered managed section into `dir`/.gitignore and records the
+/// template versions in ignr.lock. An up-to-date .gitignore is left untouched.
+fn write_managed_section(
+    dir: &Path,
+    full_content: String,
+    append: bool,
+    force: bool,
+    merged: &Merged,
+) -> Result<Written> {
+    let gitignore_path = dir.join(".gitignore");
+    let existing = read_existing_gitignore(&gitignore_path)?;
+    let final_content = updated_gitignore(
+        existing.as_deref(),
+        full_content,
+        append,
+        force,
+        &gitignore_path,
+    )?;
+
+    let changed = existing.as_deref() != Some(final_content.as_str());
+    if changed {
+        fs::write(&gitignore_path, final_content)
+            .with_context(|| format!("writing .gitignore to {}", gitignore_path.display()))?;
+    }
+
+    let lock_path = dir.join(lock::LOCK_FILE);
+    if Lockfile::from_resolved(&merged.resolved).save(&lock_path)? {
+        info!("Updated {}", lock_path.display());
+    }
+    Ok(Written { lock_path, changed })
+}
+
+fn report_no_templates(ctx: &RuntimeContext) {
+    if ctx.common.json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "detected": [],
+                "message": "No technologies detected and none specified"
+            })
+        );
+    } else if ctx.common.yaml {
+        println!("detected: []\nmessage: No technologies detected and none specified");
+    } else {
+        println!("No technologies detected and none specified. Use --add to specify templates.");
+    }
+}
+
+fn read_existing_gitignore(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read_to_string(path)
+        .map(Some)
+        .context("reading existing .gitignore")
+}
+
+/// Computes the full .gitignore content after inserting the managed section.
+///
+/// Default behavior preserves existing content and appends or updates the
+/// managed section in place.
+fn updated_gitignore(
+    existing: Option<&str>,
+    full_content: String,
+    append: bool,
+    force: bool,
+    gitignore_path: &Path,
+) -> Result<String> {
+    let Some(existing) = existing else {
+        return Ok(full_content);
+    };
+
+    if append {
+        // Pure append mode: always add to end, even if managed section exists
+        Ok(format!("{existing}\n{full_content}"))
+    } else if let Some(section) = managed::find(existing) {
+        // Replace existing managed section only, keeping content around it
+        if !force {
+            let modified = section.is_modified().with_context(|| {
+                format!(
+                    "The ignr section in {} has a header this version cannot read; \
+                     re-run with --force to overwrite it",
+                    gitignore_path.display()
+                )
+            })?;
+            if modified {
+                return Err(anyhow!(
+                    "The ignr section in {} was edited by hand. Move custom patterns below \
+                     '{}' or re-run with --force to overwrite them.",
+                    gitignore_path.display(),
+                    managed::END_MARKER
+                ));
+            }
+        }
+        Ok(managed::splice(existing, &section, &full_content))
+    } else {
+        // No existing managed section: append to existing content
+        let existing_trimmed = existing.trim_end();
+        if existing_trimmed.is_empty() {
+            Ok(full_content)
+        } else {
+            Ok(format!("{existing_trimmed}\n\n{full_content}"))
+        }
+    }
+}
+
+/// Adds or removes templates from the set recorded in the managed header and
+/// regenerates the managed section, leaving the rest of the file alone.
+///
+/// The edit is also recorded as `added` / `removed` in the header, so that
+/// `generate` keeps it when it detects the templates again.
+fn handle_edit(ctx: &RuntimeContext, cmd: EditCommand, op: EditOp) -> Result<()> {
+    let dir = resolve_scan_dir(cmd.dir.as_deref());
+    let gitignore_path = dir.join(".gitignore");
+    let existing = read_existing_gitignore(&gitignore_path)?.unwrap_or_default();
+    let section = managed::find(&existing);
+    if section.is_none() && op == EditOp::Remove {
+        return Err(anyhow!(
+            "{} has no ignr section to remove templates from",
+            gitignore_path.display()
+        ));
+    }
+    let header = section.map(|s| s.header()).transpose()?.unwrap_or_default();
+    let recorded: BTreeSet<String> = header.templates.iter().cloned().collect();
+
+    let index = TemplateManager::new(&ctx.config, &ctx.paths.data_dir).name_index();
+    let mut templates = recorded.clone();
+    let mut edits_added: BTreeSet<String> = header.added.iter().cloned().collect();
+    let mut edits_removed: BTreeSet<String> = header.removed.iter().cloned().collect();
+    for name in &cmd.templates {
+        match op {
+            EditOp::Add => {
+                let Some(canonical) = index.canonical(name) else {
+                    return Err(anyhow!("{}", index.not_found_message(name)));
+                };
+                templates.insert(canonical.clone());
+                edits_removed.remove(&canonical);
+                edits_added.insert(canonical);
+            }
+            EditOp::Remove => {
+                let canonical = index.canonical(name).unwrap_or_else(|| name.to_lowercase());
+                if !templates.remove(&canonical) {
+                    warn!(
+                        "Template '{name}' is not in the ignr section of {}",
+                        gitignore_path.display()
+                    );
+                    continue;
+                }
+                edits_added.remove(&canonical);
+                edits_removed.insert(canonical);
             }
+        }
+    }
+
+    let added: Vec<String> = templates.difference(&recorded).cloned().collect();
+    let removed: Vec<String> = recorded.difference(&templates).cloned().collect();
+    let edits_added: Vec<String> = edits_added.into_iter().collect();
+    let edits_removed: Vec<String> = edits_removed.into_iter().collect();
+
+    if added.is_empty()
+        && removed.is_empty()
+        && edits_added == header.added
+        && edits_removed == header.removed
+    {
+        if !ctx.common.quiet && !ctx.common.json && !ctx.common.yaml {
+            println!("No changes to {}", gitignore_path.display());
+        }
+        return Ok(());
+    }
+
+    let selection = Selection {
+        templates: templates.iter().cloned().collect(),
+        excluded: header
+            .excluded
+            .iter()
+            .filter(|t| !templates.contains(*t))
+            .cloned()
+            .collect(),
+        patterns: custom_patterns(ctx, &header.patterns),
+        added: edits_added,
+        removed: edits_removed,
+    };
+
+    if ctx.common.dry_run {
+        info!(
+            "dry-run: would update {} with: {}",
+            gitignore_path.display(),
+            selection.templates.join(", ")
+        );
+        return Ok(());
+    }
+
+    let merged = merge_selected(ctx, &cmd.lock, &dir, &selection)?;
+    let full_content = render_section(ctx, &dir, &selection, &merged, cmd.no_date, cmd.force)?;
+    write_managed_section(&dir, full_content, false, cmd.force, &merged)?;
+
+    if ctx.common.json || ctx.common.yaml {
+        let report = serde_json::json!({
+            "path": gitignore_path.display().to_string(),
+            "added": added,
+            "removed": removed,
+            "templates": selection.templates,
+        });
+        if ctx.common.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!("{}", serde_yaml::to_string(&report)?);
+        }
+    } else if !ctx.common.quiet {
+        if !added.is_empty() {
+            println!("Added: {}", added.join(", "));
+        }
+        if !removed.is_empty() {
+            println!("Removed: {}", removed.join(", "));
+        }
+        println!("Templates: {}", selection.templates.join(", "));
+    }
+
+    Ok(())
+}
+
+/// The current .gitignore in `dir` and what generate would write in its place
+fn preview_gitignore(
+    ctx: &RuntimeContext,
+    cmd: &DiffCommand,
+    dir: &Path,
+    selection: &Selection,
+) -> Result<(String, String)> {
+    let merged = merge_selected(ctx, &cmd.selection.lock, dir, selection)?;
+    let full_content = render_section(ctx, dir, selection, &merged, cmd.no_date, cmd.force)?;
+
+    let gitignore_path = dir.join(".gitignore");
+    let existing = read_existing_gitignore(&gitignore_path)?;
+    let updated = updated_gitignore(
+        existing.as_deref(),
+        full_content,
+        cmd.append,
+        cmd.force,
+        &gitignore_path,
+    )?;
+    Ok((existing.unwrap_or_default(), updated))
+}
+
+fn handle_diff(ctx: &RuntimeContext, cmd: DiffCommand) -> Result<()> {
+    let dir = cmd.selection.resolve_dir();
+    let selection = select_templates(ctx, &cmd.selection, &dir)?;
+    let template_list = &selection.templates;
+
+    if selection.is_empty() {
+        report_no_templates(ctx);
+        return Ok(());
+    }
+
+    let (current, updated) = preview_gitignore(ctx, &cmd, &dir, &selection)?;
+    let gitignore_path = dir.join(".gitignore");
+    let diff = diff::unified(&current, &updated, "a/.gitignore", "b/.gitignore");
+
+    if ctx.common.json || ctx.common.yaml {
+        let report = serde_json::json!({
+            "path": gitignore_path.display().to_string(),
+            "detected": template_list,
+            "changed": !diff.is_empty(),
+            "diff": diff,
+        });
+        if ctx.common.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
         } else {
-            // No existing managed section: append to existing content
-            let existing_trimmed = existing.trim_end();
-            if existing_trimmed.is_empty() {
-                full_content
+            println!("{}", serde_yaml::to_string(&report)?);
+        }
+    } else if diff.is_empty() {
+        if !ctx.common.quiet {
+            println!("No changes to {}", gitignore_path.display());
+        }
+    } else if ctx.color_enabled(io::stdout().is_terminal()) {
+        print!("{}", diff::colorize(&diff));
+    } else {
+        print!("{diff}");
+    }
+
+    Ok(())
+}
+
+fn handle_detect(ctx: &RuntimeContext, cmd: DetectCommand) -> Result<()> {
+    let dir = resolve_scan_dir(cmd.dir.as_deref());
+    let rules = load_rules(ctx)?;
+    let detection = detect_technologies(&dir, &ctx.config.detection, &rules, cmd.depth)?;
+    let candidates: Vec<_> = detection.templates.values().collect();
+    let min_score = ctx.config.detection.min_score;
+
+    if ctx.common.json {
+        println!("{}", serde_json::to_string_pretty(&candidates)?);
+    } else if ctx.common.yaml {
+        println!("{}", serde_yaml::to_string(&candidates)?);
+    } else if cmd.explain {
+        for template in &candidates {
+            let noun = if template.matches == 1 {
+                "match"
+            } else {
+                "matches"
+            };
+            let status = if template.accepted {
+                String::new()
             } else {
-                format!("{existing_trimmed}\n\n{full_content}")
+                format!(", below min_score {min_score}")
+            };
+            println!(
+                "{} (score {:.2}, {} {noun}{status})",
+                template.template, template.score, template.matches
+            );
+            for evidence in &template.evidence {
+                let path = if evidence.path.is_empty() {
+                    "-"
+                } else {
+                    evidence.path.as_str()
+                };
+                println!(
+                    "  {} '{}': {path} +{:.2} ({})",
+                    evidence.kind.as_str(),
+                    evidence.matched,
+                    evidence.weight,
+                    evidence.source
+                );
             }
         }
     } else {
-        full_content
+        for template in candidates.iter().filter(|t| t.accepted) {
+            println!("{} ({:.2})", template.template, template.score);
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct DriftReport {
+    path: String,
+    up_to_date: bool,
+    added_templates: Vec<String>,
+    removed_templates: Vec<String>,
+    added_lines: Vec<String>,
+    removed_lines: Vec<String>,
+    diff: String,
+}
+
+fn handle_check(ctx: &RuntimeContext, cmd: CheckCommand) -> Result<()> {
+    let dir = cmd.selection.resolve_dir();
+    let selection = select_templates(ctx, &cmd.selection, &dir)?;
+    let template_list = &selection.templates;
+    let content = merge_selected(ctx, &cmd.selection.lock, &dir, &selection)?.content;
+
+    let gitignore_path = dir.join(".gitignore");
+    let existing = read_existing_gitignore(&gitignore_path)?.unwrap_or_default();
+
+    let section = managed::find(&existing);
+    let current_templates = section
+        .as_ref()
+        .map(|s| s.recorded_templates())
+        .transpose()?
+        .unwrap_or_default();
+    let current_body = section.as_ref().map(|s| s.body.trim()).unwrap_or("");
+    let expected_body = content.trim();
+
+    let changes = diff::line_changes(current_body, expected_body);
+    let report = DriftReport {
+        path: gitignore_path.display().to_string(),
+        up_to_date: current_body == expected_body,
+        added_templates: template_list
+            .iter()
+            .filter(|t| !current_templates.contains(t))
+            .cloned()
+            .collect(),
+        removed_templates: current_templates
+            .iter()
+            .filter(|t| !template_list.contains(t))
+            .cloned()
+            .collect(),
+        added_lines: changes.added,
+        removed_lines: changes.removed,
+        diff: diff::unified(
+            current_body,
+            expected_body,
+            &format!("{} (current)", gitignore_path.display()),
+            &format!("{} (expected)", gitignore_path.display()),
+        ),
     };
 
-    fs::write(&gitignore_path, final_content)
-        .with_context(|| format!("writing .gitignore to {}", gitignore_path.display()))?;
+    if ctx.common.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if ctx.common.yaml {
+        println!("{}", serde_yaml::to_string(&report)?);
+    } else if report.up_to_date {
+        if !ctx.common.quiet {
+            println!("{} is up to date", gitignore_path.display());
+        }
+    } else {
+        if !report.added_templates.is_empty() {
+            println!("Templates to add: {}", report.added_templates.join(", "));
+        }
+        if !report.removed_templates.is_empty() {
+            println!(
+                "Templates to remove: {}",
+                report.removed_templates.join(", ")
+            );
+        }
+        print!("{}", report.diff);
+    }
 
-    if !ctx.common.quiet {
-        println!("Generated .gitignore with: {}", template_list.join(", "));
+    if !report.up_to_date {
+        return Err(anyhow!(
+            "managed section of {} is out of date (run `{APP_NAME} generate`)",
+            gitignore_path.display()
+        ));
     }
 
     Ok(())
 }
 
+/// Result of syncing one template source
+#[derive(Debug, Serialize)]
+struct SourceSyncReport {
+    source: String,
+    #[serde(flatten)]
+    report: sync::SyncReport,
+}
+
 fn handle_sync(ctx: &RuntimeContext, cmd: SyncCommand) -> Result<()> {
-    let url = cmd
-        .url
-        .or_else(|| ctx.config.templates.template_url.clone())
-        .ok_or_else(|| {
-            anyhow!("No template URL configured. Set templates.template_url in config or use --url")
-        })?;
+    let sources = templates::sources(&ctx.config);
+    let targets: Vec<&templates::Source> = match cmd.source {
+        Some(ref name) => {
+            let source = sources
+                .iter()
+                .find(|source| source.name == *name)
+                .ok_or_else(|| anyhow!("No template source named '{name}'"))?;
+            if !source.kind.is_synced() {
+                return Err(anyhow!(
+                    "Template source '{name}' is a {} source and is not synced",
+                    source.kind
+                ));
+            }
+            vec![source]
+        }
+        None => sources
+            .iter()
+            .filter(|source| source.kind.is_synced())
+            .collect(),
+    };
+    if targets.is_empty() {
+        return Err(anyhow!(
+            "No template source to sync. Add an http-api or git-tarball source to templates.sources"
+        ));
+    }
+    if targets.len() > 1 && (cmd.url.is_some() || cmd.github.is_some()) {
+        return Err(anyhow!(
+            "--url and --github sync a single source; pick one with --source"
+        ));
+    }
 
-    let templates_dir = ctx.paths.data_dir.join("templates");
+    let several = targets.len() > 1;
+    let mut reports = Vec::new();
+    let mut failed_sources = Vec::new();
+    for source in targets {
+        let prefix = if several {
+            format!("{}: ", source.name)
+        } else {
+            String::new()
+        };
+        match sync_source(ctx, &cmd, source, &prefix) {
+            Ok(Some(report)) => {
+                // Failed templates without --allow-failures mean nothing was installed
+                if !report.installed && !report.failed.is_empty() {
+                    failed_sources.push(source.name.clone());
+                }
+                reports.push(SourceSyncReport {
+                    source: source.name.clone(),
+                    report,
+                });
+            }
+            Ok(None) => {}
+            Err(err) if !several => return Err(err),
+            Err(err) => {
+                warn!("Could not sync template source '{}': {err:#}", source.name);
+                failed_sources.push(source.name.clone());
+            }
+        }
+    }
+
+    if ctx.common.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else if ctx.common.yaml {
+        println!("{}", serde_yaml::to_string(&reports)?);
+    }
+
+    if failed_sources.is_empty() {
+        Ok(())
+    } else if !several {
+        Err(anyhow!(
+            "Some templates could not be synced, so none were installed"
+        ))
+    } else {
+        Err(anyhow!(
+            "Could not sync template sources: {}",
+            failed_sources.join(", ")
+        ))
+    }
+}
+
+/// Syncs (or rolls back) one source, returning its report for `--json`/`--yaml`.
+///
+/// Text output is prefixed with `prefix`.
+fn sync_source(
+    ctx: &RuntimeContext,
+    cmd: &SyncCommand,
+    source: &templates::Source,
+    prefix: &str,
+) -> Result<Option<sync::SyncReport>> {
+    let layout = source.sync_layout(&ctx.paths.data_dir, &ctx.paths.cache_dir);
+
+    if cmd.rollback {
+        if ctx.common.dry_run {
+            info!(
+                "dry-run: would restore templates from {}",
+                layout.previous.display()
+            );
+            return Ok(None);
+        }
+        layout.rollback()?;
+        if !ctx.common.quiet {
+            println!("{prefix}Restored the templates replaced by the last sync");
+        }
+        return Ok(None);
+    }
+
+    let (kind, url) = match (&cmd.github, &cmd.url) {
+        (Some(location), _) => (SourceKind::GitTarball, location.clone()),
+        (None, Some(url)) => (SourceKind::HttpApi, url.clone()),
+        (None, None) => match (source.kind, &source.location) {
+            (kind, Some(location)) => (kind, location.clone()),
+            (SourceKind::GitTarball, None) => {
+                (SourceKind::GitTarball, github::DEFAULT_TARBALL.to_string())
+            }
+            _ => {
+                return Err(anyhow!(
+                    "No template URL configured. Set templates.template_url in config or use --url"
+                ));
+            }
+        },
+    };
+    // Local checkouts and archives may be given as `~/...` or with variables
+    let is_url = url.starts_with("http://") || url.starts_with("https://");
+    let url = if kind == SourceKind::GitTarball && !is_url {
+        expand_str_path(&url)?.display().to_string()
+    } else {
+        url
+    };
 
     if ctx.common.dry_run {
         info!(
             "dry-run: would sync templates from {} to {}",
             url,
-            templates_dir.display()
+            layout.live.display()
         );
-        return Ok(());
+        return Ok(None);
     }
 
-    fs::create_dir_all(&templates_dir).context("creating templates data directory")?;
+    let options = sync::SyncOptions {
+        jobs: cmd.jobs.into(),
+        resume: !cmd.restart,
+        progress: !ctx.common.quiet && !ctx.common.json && !ctx.common.yaml,
+        ..sync::SyncOptions::default()
+    };
+    let report = if kind == SourceKind::GitTarball {
+        let templates = github::Location::parse(&url).load(&options)?;
+        if templates.is_empty() {
+            return Err(anyhow!("No templates found in {url}"));
+        }
+        layout.sync_templates(&templates)?
+    } else {
+        layout.sync(&url, &options, cmd.allow_failures)?
+    };
+
+    if !ctx.common.quiet && !ctx.common.json && !ctx.common.yaml {
+        if report.resumed > 0 {
+            println!(
+                "{prefix}Resumed unfinished sync: {} of {} templates already synced",
+                report.resumed, report.total
+            );
+        }
+        println!(
+            "{prefix}{} updated, {} unchanged, {} failed",
+            report.updated,
+            report.unchanged,
+            report.failed.len()
+        );
+        if !report.installed && !report.failed.is_empty() {
+            println!(
+                "{prefix}Kept the templates in {}. Run `{APP_NAME} sync` again to retry the failed \
+                 templates, or pass --allow-failures to install the others.",
+                layout.live.display()
+            );
+        }
+    }
 
-    // Fetch list of available templates
-    let list_url = format!("{}/list", url.trim_end_matches('/'));
-    info!("Fetching template list from {}", list_url);
+    Ok(Some(report))
+}
 
-    let client = reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()
-        .context("building HTTP client")?;
+fn handle_list(ctx: &RuntimeContext, cmd: ListCommand) -> Result<()> {
+    if cmd.rules {
+        return handle_list_rules(ctx);
+    }
 
-    let response = client
-        .get(&list_url)
-        .send()
-        .context("fetching template list")?;
+    let manager = TemplateManager::new(&ctx.config, &ctx.paths.data_dir);
+    let templates = manager.describe_available();
 
-    if !response.status().is_success() {
-        return Err(anyhow!(
-            "Failed to fetch template list: HTTP {}",
-            response.status()
-        ));
+    if ctx.common.json {
+        println!("{}", serde_json::to_string_pretty(&templates)?);
+    } else if ctx.common.yaml {
+        println!("{}", serde_yaml::to_string(&templates)?);
+    } else {
+        let width = templates.iter().map(|t| t.name.len()).max().unwrap_or(0);
+        for template in &templates {
+            let mut line = format!("{:width$}", template.name);
+            if let Some(ref description) = template.meta.description {
+                line.push_str(&format!("  {description}"));
+            }
+            if !template.meta.tags.is_empty() {
+                line.push_str(&format!(" [{}]", template.meta.tags.join(", ")));
+            }
+            println!("{}", line.trim_end());
+        }
     }
 
-    let list_text = response.text().context("reading template list")?;
-    let templates: Vec<&str> = list_text.lines().collect();
+    Ok(())
+}
+
+/// The copy of a template generate would use in `dir`, plus (with
+/// `all_sources`) every shadowed copy and its diff against the active one
+fn show_template(
+    ctx: &RuntimeContext,
+    template: &str,
+    dir: &Path,
+    all_sources: bool,
+) -> Result<(
+    templates::ResolvedTemplate,
+    Vec<(templates::ResolvedTemplate, String)>,
+)> {
+    let manager = TemplateManager::new(&ctx.config, &ctx.paths.data_dir);
+    let index = manager.name_index();
+    let Some(name) = index.canonical(template) else {
+        return Err(anyhow!("{}", index.not_found_message(template)));
+    };
+
+    // Resolve the way generate would in this directory, honoring ignr.lock
+    let lock = Lockfile::load(&dir.join(lock::LOCK_FILE))?;
+    let active = TemplateManager::new(&ctx.config, &ctx.paths.data_dir)
+        .with_lock(lock.as_ref(), false)
+        .resolve(&name)?
+        .ok_or_else(|| anyhow!("{}", index.not_found_message(template)))?;
+
+    let shadowed = if all_sources {
+        manager
+            .candidates(&name)
+            .into_iter()
+            .filter(|candidate| candidate.source != active.source)
+            .map(|candidate| {
+                let diff = diff::unified(
+                    &candidate.content,
+                    &active.content,
+                    &format!("{name} ({})", candidate.source),
+                    &format!("{name} ({})", active.source),
+                );
+                (candidate, diff)
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Ok((active, shadowed))
+}
+
+fn handle_show(ctx: &RuntimeContext, cmd: ShowCommand) -> Result<()> {
+    let (active, shadowed) =
+        show_template(ctx, &cmd.template, &resolve_scan_dir(None), cmd.all_sources)?;
+    let name = &active.name;
 
+    if ctx.common.json || ctx.common.yaml {
+        let shadowed: Vec<_> = shadowed
+            .iter()
+            .map(|(template, diff)| {
+                serde_json::json!({
+                    "source": template.source,
+                    "path": template.path,
+                    "content": template.content,
+                    "diff": diff,
+                })
+            })
+            .collect();
+        let report = serde_json::json!({
+            "name": name,
+            "source": active.source,
+            "path": active.path,
+            "content": active.content,
+            "shadowed": shadowed,
+        });
+        if ctx.common.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!("{}", serde_yaml::to_string(&report)?);
+        }
+        return Ok(());
+    }
+
+    let color = ctx.color_enabled(io::stdout().is_terminal());
     if !ctx.common.quiet {
-        println!("Found {} templates", templates.len());
+        println!("# {name}: {}", describe_origin(&active));
+    }
+    print!("{}", active.content);
+
+    for (template, diff) in &shadowed {
+        println!();
+        println!("# shadowed: {}", describe_origin(template));
+        if diff.is_empty() {
+            println!("# (identical to the {} copy)", active.source);
+        } else if color {
+            print!("{}", diff::colorize(diff));
+        } else {
+            print!("{diff}");
+        }
     }
 
-    let mut synced = 0;
-    let mut failed = 0;
+    Ok(())
+}
 
-    for template in &templates {
-        let template_name = template.trim().to_lowercase();
-        if template_name.is_empty() {
-            continue;
-        }
+fn handle_search(ctx: &RuntimeContext, cmd: SearchCommand) -> Result<()> {
+    let manager = TemplateManager::new(&ctx.config, &ctx.paths.data_dir);
+    let templates: Vec<_> = manager
+        .describe_available()
+        .into_iter()
+        .filter_map(|info| {
+            let body = manager.candidates(&info.name).first()?.body().to_string();
+            Some((info, body))
+        })
+        .collect();
+    let mut hits = search::search(&templates, &cmd.terms);
+    hits.truncate(cmd.limit);
 
-        let template_url = format!("{}/{}", url.trim_end_matches('/'), template_name);
-        debug!("Fetching template: {}", template_name);
-
-        match client.get(&template_url).send() {
-            Ok(resp) if resp.status().is_success() => {
-                if let Ok(content) = resp.text() {
-                    let path = templates_dir.join(format!("{template_name}.gitignore"));
-                    if fs::write(&path, &content).is_ok() {
-                        synced += 1;
-                        debug!("Saved: {}", template_name);
-                    } else {
-                        failed += 1;
-                        warn!("Failed to write: {}", template_name);
-                    }
-                }
-            }
-            Ok(resp) => {
-                failed += 1;
-                debug!("HTTP {} for template: {}", resp.status(), template_name);
+    if ctx.common.json {
+        println!("{}", serde_json::to_string_pretty(&hits)?);
+    } else if ctx.common.yaml {
+        println!("{}", serde_yaml::to_string(&hits)?);
+    } else if hits.is_empty() {
+        if !ctx.common.quiet {
+            println!("No templates match '{}'", cmd.terms.join(" "));
+        }
+    } else {
+        for hit in &hits {
+            match hit.description {
+                Some(ref description) => println!("{} ({})  {description}", hit.name, hit.score),
+                None => println!("{} ({})", hit.name, hit.score),
             }
-            Err(e) => {
-                failed += 1;
-                debug!("Failed to fetch {}: {}", template_name, e);
+            if !ctx.common.quiet {
+                for found in &hit.matches {
+                    println!("    {}: {}", found.field.as_str(), found.text);
+                }
             }
         }
     }
 
-    if !ctx.common.quiet {
-        println!("Synced {} templates ({} failed)", synced, failed);
-    }
-
     Ok(())
 }
 
-fn handle_list(ctx: &RuntimeContext) -> Result<()> {
+/// "custom (/path/to/name.gitignore)" or "embedded"
+fn describe_origin(template: &templates::ResolvedTemplate) -> String {
+    match template.path {
+        Some(ref path) => format!("{} ({})", template.source, path.display()),
+        None => template.source.to_string(),
+    }
+}
+
+/// Loads the configured detection rules plus the hints declared by templates
+fn load_rules(ctx: &RuntimeContext) -> Result<RuleSet> {
+    let mut rules = RuleSet::load(&ctx.config.detection)?;
     let manager = TemplateManager::new(&ctx.config, &ctx.paths.data_dir);
-    let templates = manager.list_available();
+    rules.add_template_hints(&manager.describe_available())?;
+    Ok(rules)
+}
+
+fn handle_list_rules(ctx: &RuntimeContext) -> Result<()> {
+    let rules = load_rules(ctx)?;
+    let rules: Vec<_> = rules.rules().collect();
 
     if ctx.common.json {
-        println!("{}", serde_json::to_string_pretty(&templates)?);
+        println!("{}", serde_json::to_string_pretty(&rules)?);
     } else if ctx.common.yaml {
-        println!("{}", serde_yaml::to_string(&templates)?);
+        println!("{}", serde_yaml::to_string(&rules)?);
     } else {
-        for name in &templates {
-            println!("{name}");
+        for rule in &rules {
+            println!(
+                "{} [{}] {} ({})",
+                rule.template,
+                rule.category.as_str(),
+                detect::describe_rule(rule),
+                rule.source
+            );
         }
     }
 
@@ -1025,6 +1893,10 @@ fn handle_list(ctx: &RuntimeContext) -> Result<()> {
 }
 
 fn handle_init(ctx: &RuntimeContext, cmd: InitCommand) -> Result<()> {
+    if cmd.project {
+        return init_project_config(ctx, &cmd);
+    }
+
     if ctx.paths.config_file.exists() && !(cmd.force || ctx.common.assume_yes) {
         return Err(anyhow!(
             "config already exists at {} (use --force to overwrite)",
@@ -1049,6 +1921,30 @@ fn handle_init(ctx: &RuntimeContext, cmd: InitCommand) -> Result<()> {
     Ok(())
 }
 
+fn init_project_config(ctx: &RuntimeContext, cmd: &InitCommand) -> Result<()> {
+    let path = resolve_scan_dir(None).join(project::PROJECT_CONFIG_FILE);
+    if path.exists() && !(cmd.force || ctx.common.assume_yes) {
+        return Err(anyhow!(
+            "project config already exists at {} (use --force to overwrite)",
+            path.display()
+        ));
+    }
+
+    if ctx.common.dry_run {
+        info!("dry-run: would write project config to {}", path.display());
+        return Ok(());
+    }
+
+    fs::write(&path, project::default_project_config())
+        .with_context(|| format!("writing project config to {}", path.display()))?;
+
+    if !ctx.common.quiet {
+        println!("Created project config at {}", path.display());
+    }
+
+    Ok(())
+}
+
 fn handle_config(ctx: &RuntimeContext, command: ConfigCommand) -> Result<()> {
     match command {
         ConfigCommand::Show => {
@@ -1070,6 +1966,9 @@ fn handle_config(ctx: &RuntimeContext, command: ConfigCommand) -> Result<()> {
         }
         ConfigCommand::Path => {
             println!("{}", ctx.paths.config_file.display());
+            if let Some(ref project_config) = ctx.paths.project_config {
+                info!("project config: {}", project_config.display());
+            }
             Ok(())
         }
         ConfigCommand::Reset => {
@@ -1108,20 +2007,31 @@ fn load_or_init_config(paths: &mut AppPaths, common: &CommonOpts) -> Result<AppC
     }
 
     let env_prefix = env_prefix();
-    let built = Config::builder()
+    let mut builder = Config::builder()
         .set_default("templates.prefer_local", true)?
         .set_default("detection.max_depth", 10_i64)?
         .set_default("detection.detect_os", true)?
         .set_default("detection.detect_ide", true)?
+        .set_default("detection.builtin_rules", true)?
+        .set_default("detection.min_score", 1.0)?
         .add_source(
             File::from(paths.config_file.as_path())
                 .format(FileFormat::Toml)
                 .required(false),
-        )
+        );
+
+    if let Some(ref project_config) = paths.project_config {
+        info!("Using project config {}", project_config.display());
+        let text = project::read_project_config(project_config)?;
+        builder = builder.add_source(File::from_str(&text, FileFormat::Toml));
+    }
+
+    let built = builder
         .add_source(Environment::with_prefix(env_prefix.as_str()).separator("__"))
         .build()?;
 
     let config: AppConfig = built.try_deserialize()?;
+    templates::check_sources(&config.templates.sources)?;
     Ok(config)
 }
 
@@ -1147,6 +2057,22 @@ prefer_local = true
 # Templates to always include in generated .gitignore
 # always_include = ["macos", "vscode"]
 
+# Individual patterns written to a "custom" section of the managed block
+# extra_patterns = ["/scratch/", "*.local.env"]
+
+# Extra names for templates, e.g. `ignr generate --add tsx`
+[templates.aliases]
+# tsx = "node"
+
+# Named template sources, highest priority first; replace template_dir and
+# template_url when defined. kind: dir, http-api, git-tarball or embedded
+# [[templates.sources]]
+# name = "company"
+# kind = "http-api"
+# location = "https://gitignore.example.com/api"
+# priority = 20
+# namespace = "corp"
+
 [detection]
 # Maximum directory depth to scan for technology detection
 max_depth = 10
@@ -1157,6 +2083,27 @@ detect_os = true
 # Whether to detect IDE/editor directories and add patterns
 detect_ide = true
 
+# Whether to load the built-in detection rules
+builtin_rules = true
+
+# Extra detection rule files (see `ignr list --rules` for the effective set)
+# rule_files = ["~/.config/ignr/rules.toml"]
+
+# Minimum score for a template to be detected (see `ignr detect --explain`)
+# A manifest scores 1.0, each file with a matching extension 0.25
+min_score = 1.0
+
+# Directories whose contents count less towards detection
+# vendor_dirs = ["vendor", "third_party", "fixtures", "testdata"]
+
+# Templates detection should never add (always_include and --add still win)
+# exclude = ["cpp"]
+
+[header]
+# Record the generation date in the managed section header
+# Set to false for fully reproducible output
+date = true
+
 [paths]
 # Override the data directory (defaults to XDG_DATA_HOME/ignr)
 # Synced and embedded templates are stored here
@@ -1250,14 +2197,274 @@ impl fmt::Display for AppPaths {
             self.config_file.display(),
             self.data_dir.display(),
             self.cache_dir.display(),
-        )
+        )?;
+        if let Some(ref project_config) = self.project_config {
+            write!(f, ", project: {}", project_config.display())?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::resolve_base;
-    use std::path::PathBuf;
+    use super::*;
+
+    /// A quiet context with its directories below `root` and a config that
+    /// makes detection and headers independent of the machine and date
+    fn context(root: &Path) -> RuntimeContext {
+        let mut config = AppConfig::default();
+        config.detection.detect_os = false;
+        config.detection.detect_ide = false;
+        config.header.date = false;
+        RuntimeContext {
+            common: Cli::parse_from(["ignr", "--quiet", "list"]).common,
+            paths: AppPaths {
+                config_file: root.join("config.toml"),
+                project_config: None,
+                data_dir: root.join("data"),
+                cache_dir: root.join("cache"),
+            },
+            config,
+        }
+    }
+
+    /// Runs `ignr <args>` in `ctx`
+    fn run_args(ctx: &RuntimeContext, args: &[&str]) -> Result<()> {
+        let argv = std::iter::once("ignr").chain(args.iter().copied());
+        run(ctx, Cli::parse_from(argv).command)
+    }
+
+    /// A git repository below `root` in which `rust` is detected
+    fn rust_project(root: &Path) -> PathBuf {
+        let dir = root.join("project");
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join("Cargo.toml"), "[package]\n").unwrap();
+        dir
+    }
+
+    fn recorded(dir: &Path) -> managed::Header {
+        previous_header(&dir.join(".gitignore")).unwrap().unwrap()
+    }
+
+    fn selection_args(args: &[&str]) -> SelectionArgs {
+        let argv = ["ignr", "generate"].into_iter().chain(args.iter().copied());
+        match Cli::parse_from(argv).command {
+            Command::Generate(cmd) => cmd.selection,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn embedded_templates_do_not_replace_an_interrupted_sync() {
+        let root = tempfile::tempdir().unwrap();
+        let mut ctx = context(root.path());
+        let interrupt = |store: &Path| {
+            let incoming = store.join(".templates.incoming");
+            fs::create_dir_all(&incoming).unwrap();
+            fs::write(incoming.join("synced.gitignore"), "/synced/\n").unwrap();
+        };
+        let synced_names = |store: &Path| -> Vec<_> {
+            assert!(!store.join(".templates.incoming").exists());
+            fs::read_dir(store.join(sync::TEMPLATES_DIR))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect()
+        };
+
+        interrupt(&ctx.paths.data_dir);
+        ctx.recover_interrupted_syncs();
+        ctx.ensure_embedded_templates().unwrap();
+        assert_eq!(synced_names(&ctx.paths.data_dir), ["synced.gitignore"]);
+
+        // Named sources keep their templates below sources/<name>
+        ctx.config.templates.sources = vec![SourceConfig {
+            name: "team".to_string(),
+            kind: SourceKind::HttpApi,
+            location: Some("https://example.com/api".to_string()),
+            priority: 0,
+            namespace: None,
+        }];
+        let team = ctx.paths.data_dir.join("sources").join("team");
+        interrupt(&team);
+        ctx.recover_interrupted_syncs();
+        assert_eq!(synced_names(&team), ["synced.gitignore"]);
+    }
+
+    #[test]
+    fn show_reports_the_active_copy_and_what_it_shadows() {
+        let root = tempfile::tempdir().unwrap();
+        let mut ctx = context(root.path());
+        let custom = root.path().join("custom");
+        let synced = ctx.paths.data_dir.join(sync::TEMPLATES_DIR);
+        fs::create_dir_all(&custom).unwrap();
+        fs::create_dir_all(&synced).unwrap();
+        fs::write(custom.join("python.gitignore"), "/custom-venv/\n").unwrap();
+        fs::write(synced.join("python.gitignore"), "/synced-venv/\n").unwrap();
+        ctx.config.templates.template_dir = Some(custom.display().to_string());
+
+        let (active, shadowed) = show_template(&ctx, "Python", root.path(), false).unwrap();
+        assert_eq!(active.name, "python");
+        assert_eq!(
+            describe_origin(&active),
+            format!("custom ({})", custom.join("python.gitignore").display())
+        );
+        assert!(shadowed.is_empty());
+
+        let (_, shadowed) = show_template(&ctx, "python", root.path(), true).unwrap();
+        let origins: Vec<_> = shadowed.iter().map(|(t, _)| describe_origin(t)).collect();
+        assert_eq!(
+            origins,
+            [
+                format!("synced ({})", synced.join("python.gitignore").display()),
+                "embedded".to_string(),
+            ]
+        );
+        for (template, diff) in &shadowed {
+            assert!(diff.contains(&format!("--- python ({})", template.source)));
+            assert!(diff.contains("+++ python (custom)"));
+            assert!(diff.contains("+/custom-venv/"));
+        }
+        assert!(shadowed[0].1.contains("-/synced-venv/"));
+    }
+
+    /// Asserts that `ignr diff <args>` previews exactly what `ignr generate <args>` writes
+    fn assert_preview_matches_generate(ctx: &RuntimeContext, dir: &Path, args: &[&str]) {
+        let argv = ["ignr", "diff", "--dir", dir.to_str().unwrap()];
+        let Command::Diff(cmd) = Cli::parse_from(argv.iter().chain(args)).command else {
+            unreachable!()
+        };
+        let selection = select_templates(ctx, &cmd.selection, dir).unwrap();
+        let (current, preview) = preview_gitignore(ctx, &cmd, dir, &selection).unwrap();
+        assert_ne!(current, preview);
+
+        let argv = ["generate", "--dir", dir.to_str().unwrap()];
+        let argv: Vec<_> = argv.iter().chain(args).copied().collect();
+        run_args(ctx, &argv).unwrap();
+        assert_eq!(fs::read_to_string(dir.join(".gitignore")).unwrap(), preview);
+    }
+
+    #[test]
+    fn diff_previews_what_generate_writes() {
+        let root = tempfile::tempdir().unwrap();
+        let ctx = context(root.path());
+        let dir = rust_project(root.path());
+
+        // A new file
+        assert_preview_matches_generate(&ctx, &dir, &[]);
+
+        // Hand edits around and inside the managed section
+        let generated = fs::read_to_string(dir.join(".gitignore")).unwrap();
+        let edited = format!(
+            "/secrets\n\n{}",
+            generated.replace("/target/\n", "/target/\n/scratch/\n")
+        );
+        fs::write(dir.join(".gitignore"), &edited).unwrap();
+        assert_preview_matches_generate(&ctx, &dir, &["--force", "--add", "python"]);
+        let written = fs::read_to_string(dir.join(".gitignore")).unwrap();
+        assert!(written.starts_with("/secrets\n\n"));
+    }
+
+    #[test]
+    fn strict_rejects_aliases_to_unknown_templates() {
+        let root = tempfile::tempdir().unwrap();
+        let mut ctx = context(root.path());
+        let dir = rust_project(root.path());
+        ctx.config
+            .templates
+            .aliases
+            .insert("ts".to_string(), "typescript".to_string());
+
+        let args = selection_args(&["--add", "ts", "--strict"]);
+        let err = select_templates(&ctx, &args, &dir).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Alias 'ts' points to unknown template 'typescript'")
+        );
+        let selection = select_templates(&ctx, &selection_args(&["--add", "ts"]), &dir).unwrap();
+        assert_eq!(selection.templates, ["rust"]);
+    }
+
+    #[test]
+    fn exclude_applies_to_detected_templates_only() {
+        let root = tempfile::tempdir().unwrap();
+        let mut ctx = context(root.path());
+        let dir = rust_project(root.path());
+        fs::write(dir.join("package.json"), "{}\n").unwrap();
+        ctx.config.detection.exclude = vec!["rust".to_string(), "python".to_string()];
+        ctx.config.templates.always_include = vec!["python".to_string()];
+
+        let select = |args: &[&str]| select_templates(&ctx, &selection_args(args), &dir).unwrap();
+        let selection = select(&[]);
+        assert_eq!(selection.templates, ["node", "python"]);
+        assert_eq!(selection.excluded, ["rust"]);
+
+        // --exclude adds to detection.exclude, but --add still wins
+        let selection = select(&["--exclude", "node", "--add", "rust"]);
+        assert_eq!(selection.templates, ["python", "rust"]);
+        assert_eq!(selection.excluded, ["node", "rust"]);
+
+        let report = serde_json::Value::Object(selection.report());
+        assert_eq!(
+            report,
+            serde_json::json!({
+                "detected": ["python", "rust"],
+                "excluded": ["node", "rust"],
+                "patterns": [],
+            })
+        );
+    }
+
+    #[test]
+    fn add_and_remove_survive_regeneration() {
+        let root = tempfile::tempdir().unwrap();
+        let ctx = context(root.path());
+        let dir = rust_project(root.path());
+        let d = dir.to_str().unwrap();
+
+        run_args(&ctx, &["generate", "--dir", d]).unwrap();
+        assert_eq!(recorded(&dir).templates, ["rust"]);
+
+        run_args(&ctx, &["add", "python", "--dir", d]).unwrap();
+        run_args(&ctx, &["check", "--dir", d]).unwrap();
+        run_args(&ctx, &["generate", "--dir", d]).unwrap();
+        let header = recorded(&dir);
+        assert_eq!(header.templates, ["python", "rust"]);
+        assert_eq!(header.added, ["python"]);
+
+        run_args(&ctx, &["remove", "rust", "--dir", d]).unwrap();
+        run_args(&ctx, &["check", "--dir", d]).unwrap();
+        run_args(&ctx, &["generate", "--dir", d]).unwrap();
+        let header = recorded(&dir);
+        assert_eq!(header.templates, ["python"]);
+        assert_eq!(header.added, ["python"]);
+        assert_eq!(header.removed, ["rust"]);
+
+        // Adding a removed template again undoes the removal
+        run_args(&ctx, &["add", "rust", "--dir", d]).unwrap();
+        run_args(&ctx, &["generate", "--dir", d]).unwrap();
+        let header = recorded(&dir);
+        assert_eq!(header.templates, ["python", "rust"]);
+        assert_eq!(header.added, ["python", "rust"]);
+        assert!(header.removed.is_empty());
+    }
+
+    #[test]
+    fn unreadable_headers_are_only_overwritten_with_force() {
+        let existing = format!(
+            "/local\n\n{}\n# version: 3\n# hash: 0123456789abcdef\n\n/edited\n{}\n",
+            managed::START_MARKER,
+            managed::END_MARKER
+        );
+        let path = Path::new(".gitignore");
+        let err = updated_gitignore(Some(&existing), "/new\n".to_string(), false, false, path)
+            .unwrap_err();
+        assert!(err.to_string().contains("cannot read"), "{err:#}");
+
+        let forced =
+            updated_gitignore(Some(&existing), "/new\n".to_string(), false, true, path).unwrap();
+        assert!(forced.starts_with("/local\n\n/new\n"));
+        assert!(!forced.contains("version: 3"));
+    }
 
     #[test]
     fn absolute_xdg_wins_on_unix() {
@@ -0,0 +1,450 @@
+//! The ignr-managed section of a `.gitignore`.
+//!
+//! A managed section starts with a header written by `generate` and ends with
+//! [`END_MARKER`]. The header is a block of commented YAML recording the inputs
+//! of the run (templates, exclusions, custom patterns, templates added or
+//! removed with `ignr add` / `ignr remove`, lockfile hash) and a hash
+//! of the section body, so the section can be regenerated with the same inputs
+//! and hand edits inside it can be detected:
+//!
+//! ```text
+//! # ---- ignr ----
+//! # version: 2
+//! # templates: [linux, rust]
+//! # ignr: 0.1.2
+//! # hash: 51d4f05ecb878d92
+//! ```
+//!
+//! Headers written by older versions were a single line,
+//! `# ---- ignr (detected: a,b) @ DATE hash:HASH ----`, and such sections may
+//! lack the end marker and extend to the end of the file. They are still read
+//! and are rewritten in the current format on the next `generate`.
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Line that opens a managed section
+pub const START_MARKER: &str = "# ---- ignr ----";
+/// Line that closes a managed section
+pub const END_MARKER: &str = "# ---- /ignr ----";
+/// Prefix of the single-line header written before [`HEADER_VERSION`] 2
+const LEGACY_START_MARKER: &str = "# ---- ignr (";
+const LEGACY_HASH_PREFIX: &str = "hash:";
+
+/// Version of the header format written by this build
+pub const HEADER_VERSION: u32 = 2;
+
+const HASH_LEN: usize = 16;
+
+/// The inputs and checksum recorded in a managed section header
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Header {
+    /// Header format version (1 for legacy single-line headers)
+    pub version: u32,
+    pub templates: Vec<String>,
+    /// Templates that were detected but excluded
+    pub excluded: Vec<String>,
+    /// Templates added with `ignr add`, kept even when they are not detected
+    pub added: Vec<String>,
+    /// Templates removed with `ignr remove`, left out even when they are detected
+    pub removed: Vec<String>,
+    /// Patterns of the custom section
+    pub patterns: Vec<String>,
+    /// Version of ignr that wrote the section
+    pub ignr: Option<String>,
+    /// Hash of the lockfile matching the section
+    pub lock: Option<String>,
+    pub date: Option<String>,
+    /// Hash of the section body
+    pub hash: Option<String>,
+}
+
+impl Header {
+    /// A header for the current format and ignr version
+    pub fn new(templates: &[String], excluded: &[String], patterns: &[String]) -> Self {
+        Self {
+            version: HEADER_VERSION,
+            templates: templates.to_vec(),
+            excluded: excluded.to_vec(),
+            patterns: patterns.to_vec(),
+            ignr: Some(env!("CARGO_PKG_VERSION").to_string()),
+            ..Self::default()
+        }
+    }
+
+    /// Parses a header block, migrating legacy single-line headers
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+        let first = lines.next().unwrap_or_default().trim_end();
+        if first.starts_with(LEGACY_START_MARKER) {
+            return Ok(Self::parse_legacy(first));
+        }
+
+        let yaml: String = lines
+            .map(|line| {
+                let line = line.trim_end().strip_prefix('#').unwrap_or(line);
+                let line = line.strip_prefix(' ').unwrap_or(line);
+                format!("{line}\n")
+            })
+            .collect();
+        let header: Header = if yaml.trim().is_empty() {
+            Header::default()
+        } else {
+            serde_yaml::from_str(&yaml).context("parsing ignr section header")?
+        };
+        if header.version > HEADER_VERSION {
+            return Err(anyhow!(
+                "the ignr section was written by a newer version of ignr (header version {})",
+                header.version
+            ));
+        }
+        Ok(header)
+    }
+
+    /// Reads `# ---- ignr (detected: a,b) @ DATE hash:HASH ----`
+    fn parse_legacy(line: &str) -> Self {
+        let rest = line.strip_prefix(LEGACY_START_MARKER).unwrap_or(line);
+        let (list, rest) = rest.split_once(')').unwrap_or((rest, ""));
+        let templates = list
+            .strip_prefix("detected:")
+            .unwrap_or(list)
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let mut date = None;
+        let mut hash = None;
+        let mut words = rest.split_whitespace().filter(|w| *w != "----");
+        while let Some(word) = words.next() {
+            if word == "@" {
+                date = words.next().map(str::to_string);
+            } else if let Some(value) = word.strip_prefix(LEGACY_HASH_PREFIX) {
+                hash = Some(value.to_string());
+            }
+        }
+
+        Self {
+            version: 1,
+            templates,
+            date,
+            hash,
+            ..Self::default()
+        }
+    }
+
+    /// Whether `self` records the same inputs as `other` and the hash of
+    /// `content`, ignoring the date
+    pub fn describes(&self, other: &Header, content: &str) -> bool {
+        let undated = |header: &Header| Header {
+            date: None,
+            hash: None,
+            ..header.clone()
+        };
+        self.hash.as_deref() == Some(content_hash(content).as_str())
+            && undated(self) == undated(other)
+    }
+
+    /// Renders the header block, without a trailing newline
+    pub fn render(&self) -> String {
+        let mut lines = vec![
+            START_MARKER.to_string(),
+            format!("# version: {}", self.version),
+            format!("# templates: {}", flow_list(&self.templates)),
+        ];
+        let lists = [
+            ("excluded", &self.excluded),
+            ("added", &self.added),
+            ("removed", &self.removed),
+        ];
+        for (key, items) in lists {
+            if !items.is_empty() {
+                lines.push(format!("# {key}: {}", flow_list(items)));
+            }
+        }
+        if !self.patterns.is_empty() {
+            lines.push(format!("# patterns: {}", flow_list(&self.patterns)));
+        }
+        let scalars = [
+            ("ignr", &self.ignr),
+            ("lock", &self.lock),
+            ("date", &self.date),
+            ("hash", &self.hash),
+        ];
+        for (key, value) in scalars {
+            if let Some(value) = value {
+                lines.push(format!("# {key}: {}", flow_item(value)));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// Renders a YAML flow sequence, quoting items that need it
+fn flow_list(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|item| flow_item(item)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn flow_item(item: &str) -> String {
+    let plain = !item.is_empty()
+        && item
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'))
+        && !item.starts_with(['-', '.', '+']);
+    if plain {
+        item.to_string()
+    } else {
+        serde_json::to_string(item).unwrap_or_else(|_| format!("{item:?}"))
+    }
+}
+
+/// A managed section located inside an existing `.gitignore`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagedSection<'a> {
+    /// Byte offset of the start marker line
+    pub start: usize,
+    /// Byte offset just past the end marker line (or EOF for legacy sections)
+    pub end: usize,
+    /// The header lines, starting with the start marker, without a trailing newline
+    pub header: &'a str,
+    /// Everything between the header and the end marker
+    pub body: &'a str,
+    pub has_end_marker: bool,
+}
+
+impl ManagedSection<'_> {
+    /// The parsed header (legacy headers are migrated to the current structure)
+    pub fn header(&self) -> Result<Header> {
+        Header::parse(self.header)
+    }
+
+    /// Templates listed in the header
+    pub fn recorded_templates(&self) -> Result<Vec<String>> {
+        Ok(self.header()?.templates)
+    }
+
+    /// Whether the body no longer matches the hash recorded in the header.
+    ///
+    /// Sections without a recorded hash cannot be checked and are never
+    /// reported as modified. A header that cannot be parsed is an error, since
+    /// whether the section was edited is unknown.
+    pub fn is_modified(&self) -> Result<bool> {
+        Ok(self
+            .header()?
+            .hash
+            .is_some_and(|hash| hash != content_hash(self.body)))
+    }
+}
+
+/// Finds the first managed section in `text`
+pub fn find(text: &str) -> Option<ManagedSection<'_>> {
+    let mut lines = line_offsets(text).peekable();
+    let (start, first) = lines.find(|&(_, line)| {
+        let line = line.trim_end();
+        line == START_MARKER || line.starts_with(LEGACY_START_MARKER)
+    })?;
+
+    // The header continues over the comment lines directly below a current
+    // start marker; legacy headers are a single line
+    let mut header_end = start + first.len();
+    if first.trim_end() == START_MARKER {
+        while let Some(&(offset, line)) = lines.peek() {
+            let trimmed = line.trim_end();
+            if !trimmed.starts_with('#') || trimmed == END_MARKER {
+                break;
+            }
+            header_end = offset + line.len();
+            lines.next();
+        }
+    }
+    let header = text[start..header_end].trim_end_matches(['\r', '\n']);
+    let body_start = (header_end + 1).min(text.len());
+
+    let end_marker = line_offsets(&text[body_start..])
+        .find(|&(_, line)| line.trim_end() == END_MARKER)
+        .map(|(offset, line)| (body_start + offset, line));
+
+    Some(match end_marker {
+        Some((marker_start, line)) => ManagedSection {
+            start,
+            end: (marker_start + line.len() + 1).min(text.len()),
+            header,
+            body: &text[body_start..marker_start],
+            has_end_marker: true,
+        },
+        None => ManagedSection {
+            start,
+            end: text.len(),
+            header,
+            body: &text[body_start..],
+            has_end_marker: false,
+        },
+    })
+}
+
+fn line_offsets(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len() + 1;
+        Some((start, line))
+    })
+}
+
+/// Short, stable hash of a managed section body (surrounding whitespace ignored)
+pub fn content_hash(body: &str) -> String {
+    let digest = Sha256::digest(body.trim().as_bytes());
+    let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    hex[..HASH_LEN].to_string()
+}
+
+/// Renders a complete managed section, including header and end marker.
+///
+/// The hash of `content` is filled into the header.
+pub fn render(header: &Header, content: &str) -> String {
+    let header = Header {
+        hash: Some(content_hash(content)),
+        ..header.clone()
+    };
+    format!(
+        "{}\n\n{}\n\n{END_MARKER}\n",
+        header.render(),
+        content.trim_end()
+    )
+}
+
+/// Replaces `section` in `existing` with `replacement`, keeping content on both sides
+pub fn splice(existing: &str, section: &ManagedSection<'_>, replacement: &str) -> String {
+    let before = existing[..section.start].trim_end();
+    let after = existing[section.end..].trim_start_matches(['\n', '\r']);
+
+    let mut output = String::new();
+    if !before.is_empty() {
+        output.push_str(before);
+        output.push_str("\n\n");
+    }
+    output.push_str(replacement);
+    if !after.trim().is_empty() {
+        output.push('\n');
+        output.push_str(after);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(content: &str) -> String {
+        let mut header = Header::new(&["rust".to_string()], &[], &[]);
+        header.date = Some("2024-01-01".to_string());
+        render(&header, content)
+    }
+
+    #[test]
+    fn render_round_trips_through_find() {
+        let rendered = section("# === rust ===\ntarget/\n");
+        let found = find(&rendered).unwrap();
+        assert!(found.has_end_marker);
+        assert_eq!(found.start, 0);
+        assert_eq!(found.end, rendered.len());
+        assert_eq!(found.body.trim(), "# === rust ===\ntarget/");
+        assert!(!found.is_modified().unwrap());
+        assert_eq!(
+            found.recorded_templates().unwrap(),
+            vec!["rust".to_string()]
+        );
+    }
+
+    #[test]
+    fn header_round_trips() {
+        let mut header = Header::new(
+            &["node".to_string(), "c++".to_string()],
+            &["cpp".to_string()],
+            &["/scratch/".to_string(), "!keep, me".to_string()],
+        );
+        header.added = vec!["python".to_string()];
+        header.removed = vec!["linux".to_string()];
+        header.lock = Some("0123456789abcdef".to_string());
+        header.date = Some("2024-01-01".to_string());
+        header.hash = Some("fedcba9876543210".to_string());
+
+        let rendered = header.render();
+        assert!(rendered.contains("# added: [python]\n# removed: [linux]\n"));
+        assert!(rendered.contains("# patterns: [\"/scratch/\", \"!keep, me\"]"));
+        assert_eq!(Header::parse(&rendered).unwrap(), header);
+    }
+
+    #[test]
+    fn describes_ignores_the_date() {
+        let content = "# === rust ===\ntarget/\n";
+        let rendered = section(content);
+        let recorded = find(&rendered).unwrap().header().unwrap();
+
+        let mut next = Header::new(&["rust".to_string()], &[], &[]);
+        next.date = Some("2025-06-30".to_string());
+        assert!(recorded.describes(&next, content));
+        assert!(!recorded.describes(&next, "# === rust ===\n/target/\n"));
+        next.patterns.push("/scratch/".to_string());
+        assert!(!recorded.describes(&next, content));
+    }
+
+    #[test]
+    fn detects_hand_edits() {
+        let edited = section("# === rust ===\ntarget/\n").replace("target/", "target/\nfoo");
+        assert!(find(&edited).unwrap().is_modified().unwrap());
+    }
+
+    #[test]
+    fn legacy_headers_are_migrated() {
+        let text = "# ---- ignr (detected: linux,rust) @ 2024-01-01 hash:51d4f05ecb878d92 ----\n\n# === rust ===\n/target/\n\n# ---- /ignr ----\n";
+        let found = find(text).unwrap();
+        assert!(found.has_end_marker);
+        assert_eq!(found.body.trim(), "# === rust ===\n/target/");
+        let header = found.header().unwrap();
+        assert_eq!(header.version, 1);
+        assert_eq!(header.templates, vec!["linux", "rust"]);
+        assert_eq!(header.date.as_deref(), Some("2024-01-01"));
+        assert_eq!(header.hash.as_deref(), Some("51d4f05ecb878d92"));
+    }
+
+    #[test]
+    fn legacy_section_extends_to_eof() {
+        let text = "foo\n# ---- ignr (detected: rust) @ 2024-01-01 ----\n\ntarget/\n";
+        let found = find(text).unwrap();
+        assert!(!found.has_end_marker);
+        assert_eq!(found.end, text.len());
+        assert_eq!(found.header().unwrap().hash, None);
+        assert!(!found.is_modified().unwrap());
+    }
+
+    #[test]
+    fn newer_headers_are_rejected() {
+        let text = format!("{START_MARKER}\n# version: 99\n\n{END_MARKER}\n");
+        let found = find(&text).unwrap();
+        assert!(found.header().is_err());
+        assert!(found.is_modified().is_err());
+    }
+
+    #[test]
+    fn splice_preserves_content_after_end_marker() {
+        let existing = format!(
+            "/local\n\n{}\n/after-marker\n",
+            section("# === rust ===\ntarget/\n")
+        );
+        let found = find(&existing).unwrap();
+        let replacement = section("# === rust ===\ntarget/\n*.rlib\n");
+        let spliced = splice(&existing, &found, &replacement);
+        assert!(spliced.starts_with("/local\n\n# ---- ignr ----\n"));
+        assert!(spliced.ends_with("*.rlib\n\n# ---- /ignr ----\n\n/after-marker\n"));
+    }
+
+    #[test]
+    fn header_must_start_a_line() {
+        assert!(find("# note: # ---- ignr (detected: x) ----\n").is_none());
+        assert!(find("# note: # ---- ignr ----\n").is_none());
+    }
+}
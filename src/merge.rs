@@ -0,0 +1,576 @@
+//! Merging template bodies into a single `.gitignore` body.
+//!
+//! Patterns are compared by what they match rather than by their text, so
+//! `node_modules`, `node_modules/`, `/node_modules/` and `**/node_modules`
+//! collapse into the broadest of them. Comments stay attached to the patterns
+//! below them and are dropped only when all of those patterns were collapsed.
+//!
+//! Git lets the last matching pattern decide whether a path is ignored, so the
+//! merged templates are treated as one ordered list and a pattern is collapsed
+//! only if that cannot change the outcome for any path.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+
+use globset::{GlobBuilder, GlobMatcher};
+
+/// A template's expanded body, ready to be merged
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub content: String,
+}
+
+/// A pattern that was left out because another pattern already covers it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collapsed {
+    pub pattern: String,
+    pub template: String,
+    pub kept: String,
+    pub kept_template: String,
+}
+
+/// A template un-ignoring a path that another template ignores
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub negation: String,
+    pub template: String,
+    pub pattern: String,
+    pub ignoring_template: String,
+    /// Whether the negation comes last and therefore decides the outcome
+    pub negation_wins: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergeOutput {
+    pub content: String,
+    pub collapsed: Vec<Collapsed>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// A gitignore pattern reduced to what it matches
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Pattern {
+    negated: bool,
+    /// Matches relative to the `.gitignore` only, not at any depth
+    anchored: bool,
+    /// Matches directories only (trailing `/`)
+    dir_only: bool,
+    path: String,
+}
+
+impl Pattern {
+    /// Parses a line, returning `None` for blank lines and comments
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, rest) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, rest) = match rest.strip_suffix('/') {
+            Some(rest) if !rest.is_empty() => (true, rest),
+            _ => (false, rest),
+        };
+        let (anchored, path) = if let Some(path) = rest.strip_prefix('/') {
+            (true, path)
+        } else if let Some(path) = rest.strip_prefix("**/").filter(|p| !p.contains('/')) {
+            // `**/name` matches at any depth, just like `name`
+            (false, path)
+        } else {
+            // A slash in the middle anchors the pattern
+            (rest.contains('/'), rest)
+        };
+
+        Some(Self {
+            negated,
+            anchored,
+            dir_only,
+            path: path.to_string(),
+        })
+    }
+
+    /// How many of the anchoring and directory restrictions the pattern lifts
+    fn breadth(&self) -> u8 {
+        u8::from(!self.anchored) + u8::from(!self.dir_only)
+    }
+
+    /// Whether every path matched by `other` is also matched by `self`
+    fn covers(&self, other: &Pattern) -> bool {
+        self.negated == other.negated
+            && self.path == other.path
+            && (!self.anchored || other.anchored)
+            && (!self.dir_only || other.dir_only)
+    }
+}
+
+/// One line of a section, with the pattern it holds (if any)
+struct Line {
+    text: String,
+    pattern: Option<Pattern>,
+    matcher: Option<GlobMatcher>,
+    kept: bool,
+}
+
+impl Line {
+    fn new(text: &str) -> Self {
+        let pattern = Pattern::parse(text);
+        let matcher = pattern.as_ref().and_then(|p| {
+            GlobBuilder::new(&p.path)
+                .literal_separator(true)
+                .build()
+                .ok()
+                .map(|glob| glob.compile_matcher())
+        });
+        Self {
+            text: text.to_string(),
+            pattern,
+            matcher,
+            kept: true,
+        }
+    }
+
+    /// Whether the pattern matches `path` or one of its parents (or, for
+    /// unanchored patterns, any part of it). `None` if the pattern is not a
+    /// valid glob.
+    fn matches_within(&self, path: &str) -> Option<bool> {
+        let matcher = self.matcher.as_ref()?;
+        let anchored = self.pattern.as_ref().is_some_and(|p| p.anchored);
+        let parts: Vec<&str> = path.split('/').collect();
+        let starts = if anchored { 0..1 } else { 0..parts.len() };
+        Some(starts.into_iter().any(|start| {
+            (start + 1..=parts.len()).any(|end| matcher.is_match(parts[start..end].join("/")))
+        }))
+    }
+}
+
+/// Comments followed by the patterns they describe
+struct Block {
+    lines: Vec<usize>,
+}
+
+/// Merges `sections` in order, collapsing patterns that are covered by others
+pub fn merge(sections: &[Section]) -> MergeOutput {
+    // Flatten every pattern line so coverage can be checked across sections
+    let mut lines: Vec<Line> = Vec::new();
+    let mut owners: Vec<usize> = Vec::new();
+    let mut blocks: Vec<Vec<Block>> = Vec::new();
+    for (index, section) in sections.iter().enumerate() {
+        let mut section_blocks = Vec::new();
+        let mut current = Block { lines: Vec::new() };
+        let mut in_patterns = false;
+        for text in section.content.lines() {
+            let line = Line::new(text);
+            let is_blank = text.trim().is_empty();
+            // A blank line, or a comment after patterns, starts a new block
+            if is_blank || (line.pattern.is_none() && in_patterns) {
+                if !current.lines.is_empty() {
+                    section_blocks.push(current);
+                }
+                current = Block { lines: Vec::new() };
+                in_patterns = false;
+            }
+            if is_blank {
+                continue;
+            }
+            in_patterns |= line.pattern.is_some();
+            current.lines.push(lines.len());
+            lines.push(line);
+            owners.push(index);
+        }
+        if !current.lines.is_empty() {
+            section_blocks.push(current);
+        }
+        blocks.push(section_blocks);
+    }
+
+    let conflicts = find_conflicts(&lines, &owners, sections);
+    let collapsed = collapse(&mut lines, &owners, sections);
+
+    let mut content = String::new();
+    for (section, section_blocks) in sections.iter().zip(&blocks) {
+        let mut section_lines = Vec::new();
+        for block in section_blocks {
+            let has_patterns = block.lines.iter().any(|&i| lines[i].pattern.is_some());
+            let any_kept = block
+                .lines
+                .iter()
+                .any(|&i| lines[i].pattern.is_some() && lines[i].kept);
+            if has_patterns && !any_kept {
+                continue;
+            }
+            section_lines.extend(
+                block
+                    .lines
+                    .iter()
+                    .filter(|&&i| lines[i].kept)
+                    .map(|&i| lines[i].text.as_str()),
+            );
+        }
+
+        let has_patterns = section_lines
+            .iter()
+            .any(|line| Pattern::parse(line).is_some());
+        if !has_patterns {
+            continue;
+        }
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        content.push_str(&format!("# === {} ===\n", section.name));
+        for line in section_lines {
+            content.push_str(line);
+            content.push('\n');
+        }
+    }
+
+    MergeOutput {
+        content,
+        collapsed,
+        conflicts,
+    }
+}
+
+/// Marks patterns covered by another pattern as dropped.
+///
+/// Of two equivalent patterns the first is kept; a narrower pattern is dropped
+/// in favor of the broadest pattern covering it. Because the last matching
+/// pattern decides whether a path is ignored, a pattern is only dropped in
+/// favor of an earlier one if no pattern of the opposite kind in between could
+/// match the same paths. Dropping a pattern in favor of a later one is always
+/// safe, since the later one overrides it anyway.
+fn collapse(lines: &mut [Line], owners: &[usize], sections: &[Section]) -> Vec<Collapsed> {
+    let mut by_path: HashMap<(bool, &str), Vec<usize>> = HashMap::new();
+    for (index, line) in lines.iter().enumerate() {
+        if let Some(ref pattern) = line.pattern {
+            by_path
+                .entry((pattern.negated, pattern.path.as_str()))
+                .or_default()
+                .push(index);
+        }
+    }
+
+    let pattern_at = |index: usize| {
+        lines[index]
+            .pattern
+            .as_ref()
+            .expect("grouped lines have patterns")
+    };
+    let mut kept = vec![true; lines.len()];
+    let mut drops: Vec<(usize, usize)> = Vec::new();
+
+    // Keep the first copy where that cannot change which paths are ignored
+    for group in by_path.values() {
+        for &index in group {
+            let pattern = pattern_at(index);
+            let coverer = group
+                .iter()
+                .copied()
+                .filter(|&other| {
+                    other < index
+                        && kept[other]
+                        && pattern_at(other).covers(pattern)
+                        && !overridden_between(lines, other, index)
+                })
+                .max_by_key(|&other| (pattern_at(other).breadth(), Reverse(other)));
+            if let Some(other) = coverer {
+                kept[index] = false;
+                drops.push((index, other));
+            }
+        }
+    }
+
+    // Drop whatever a later pattern covers
+    for group in by_path.values() {
+        for &index in group.iter().rev() {
+            if !kept[index] {
+                continue;
+            }
+            let pattern = pattern_at(index);
+            let coverer = group
+                .iter()
+                .copied()
+                .filter(|&other| other > index && kept[other] && pattern_at(other).covers(pattern))
+                .max_by_key(|&other| (pattern_at(other).breadth(), Reverse(other)));
+            if let Some(other) = coverer {
+                kept[index] = false;
+                drops.push((index, other));
+            }
+        }
+    }
+
+    drops.sort_unstable();
+    drops
+        .into_iter()
+        .map(|(index, kept)| {
+            lines[index].kept = false;
+            Collapsed {
+                pattern: lines[index].text.trim().to_string(),
+                template: sections[owners[index]].name.clone(),
+                kept: lines[kept].text.trim().to_string(),
+                kept_template: sections[owners[kept]].name.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Whether a pattern of the opposite kind between `first` and `last` may match
+/// paths matched by `last`
+fn overridden_between(lines: &[Line], first: usize, last: usize) -> bool {
+    let target = &lines[last];
+    let negated = target.pattern.as_ref().is_some_and(|p| p.negated);
+    lines[first + 1..last].iter().any(|line| {
+        line.pattern
+            .as_ref()
+            .is_some_and(|p| p.negated != negated && may_overlap(line, target))
+    })
+}
+
+/// Conservatively decides whether two patterns can match a common path.
+///
+/// Only literal patterns are known to be disjoint, and only if neither path
+/// can contain the other (`build` matches inside `src/build/out`).
+fn may_overlap(a: &Line, b: &Line) -> bool {
+    let (Some(pa), Some(pb)) = (&a.pattern, &b.pattern) else {
+        return false;
+    };
+    if is_glob(&pa.path) || is_glob(&pb.path) {
+        return true;
+    }
+    let parts_a: Vec<&str> = pa.path.split('/').collect();
+    let parts_b: Vec<&str> = pb.path.split('/').collect();
+    contains_parts(&parts_a, &parts_b) || contains_parts(&parts_b, &parts_a)
+}
+
+/// Whether `pattern` uses glob syntax rather than naming a path literally
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '\\'])
+}
+
+/// Whether `inner` occurs as consecutive components of `outer`
+fn contains_parts(outer: &[&str], inner: &[&str]) -> bool {
+    outer.windows(inner.len()).any(|window| window == inner)
+}
+
+/// Finds negations that un-ignore what another template ignores
+fn find_conflicts(lines: &[Line], owners: &[usize], sections: &[Section]) -> Vec<Conflict> {
+    // Keyed by negation and ignoring section; whichever comes last decides
+    let mut found: BTreeMap<(usize, usize, &str), (usize, usize)> = BTreeMap::new();
+    for (negation, line) in lines.iter().enumerate() {
+        let Some(ref unignored) = line.pattern else {
+            continue;
+        };
+        if !unignored.negated {
+            continue;
+        }
+        let example = example_path(&unignored.path);
+        for (index, other) in lines.iter().enumerate() {
+            let Some(ref pattern) = other.pattern else {
+                continue;
+            };
+            let owner = owners[index];
+            if pattern.negated
+                || owner == owners[negation]
+                || !(pattern.path == unignored.path
+                    || other.matches_within(&example).unwrap_or(false))
+            {
+                continue;
+            }
+            // A template that makes the same exception agrees with the negation
+            let agrees = lines
+                .iter()
+                .zip(owners)
+                .any(|(l, &o)| o == owner && l.pattern.as_ref() == Some(unignored));
+            if !agrees {
+                let key = (owners[negation], owner, line.text.trim());
+                let last = found.entry(key).or_insert((negation, index));
+                *last = (last.0.max(negation), last.1.max(index));
+            }
+        }
+    }
+
+    found
+        .into_iter()
+        .map(|((template, owner, _), (negation, index))| Conflict {
+            negation: lines[negation].text.trim().to_string(),
+            template: sections[template].name.clone(),
+            pattern: lines[index].text.trim().to_string(),
+            ignoring_template: sections[owner].name.clone(),
+            negation_wins: negation > index,
+        })
+        .collect()
+}
+
+/// A path matched by a glob, e.g. `x.log` for `*.log` and `Packages` for `[Pp]ackages`.
+///
+/// Also used by search to check glob terms against template patterns.
+pub fn example_path(pattern: &str) -> String {
+    let mut example = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '?' => example.push('x'),
+            '\\' => example.extend(chars.next()),
+            '[' => {
+                let class: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                let class = class.trim_start_matches(['!', '^']);
+                example.extend(class.chars().next());
+            }
+            c => example.push(c),
+        }
+    }
+    example
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(name: &str, content: &str) -> Section {
+        Section {
+            name: name.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn normalizes_patterns() {
+        let bare = Pattern::parse("node_modules").unwrap();
+        assert_eq!(Pattern::parse("**/node_modules"), Some(bare.clone()));
+        let dir = Pattern::parse("node_modules/").unwrap();
+        let rooted = Pattern::parse("/node_modules/").unwrap();
+        assert!(bare.covers(&dir) && bare.covers(&rooted) && dir.covers(&rooted));
+        assert!(!rooted.covers(&dir) && !dir.covers(&bare));
+
+        assert!(Pattern::parse("docs/build").unwrap().anchored);
+        assert!(!Pattern::parse("**/docs/build").unwrap().covers(&bare));
+        assert_eq!(Pattern::parse("# comment"), None);
+        assert!(Pattern::parse("!keep.log").unwrap().negated);
+    }
+
+    #[test]
+    fn collapses_equivalent_and_covered_patterns() {
+        let output = merge(&[
+            section("a", "/node_modules/\n*.log\n"),
+            section("b", "node_modules/\n**/node_modules\n*.log\n"),
+            section("c", "node_modules\n"),
+        ]);
+        assert_eq!(
+            output.content,
+            "# === a ===\n*.log\n\n# === b ===\n**/node_modules\n"
+        );
+        let collapsed: Vec<_> = output
+            .collapsed
+            .iter()
+            .map(|c| (c.pattern.as_str(), c.kept.as_str()))
+            .collect();
+        assert_eq!(
+            collapsed,
+            vec![
+                ("/node_modules/", "**/node_modules"),
+                ("node_modules/", "**/node_modules"),
+                ("*.log", "*.log"),
+                ("node_modules", "**/node_modules"),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_follow_their_patterns() {
+        let output = merge(&[
+            section("a", "# Logs\n*.log\n"),
+            section("b", "# Logs\n*.log\n\n# Build\n# output\n/build/\n"),
+        ]);
+        assert_eq!(
+            output.content,
+            "# === a ===\n# Logs\n*.log\n\n# === b ===\n# Build\n# output\n/build/\n"
+        );
+    }
+
+    #[test]
+    fn duplicates_across_a_negation_keep_their_meaning() {
+        // `b` re-ignores what `a` un-ignored, so its `*.log` must stay last
+        let sections = [
+            section("a", "*.log\n!important.log\n"),
+            section("b", "*.log\n"),
+        ];
+        let output = merge(&sections);
+        assert_eq!(
+            output.content,
+            "# === a ===\n!important.log\n\n# === b ===\n*.log\n"
+        );
+
+        // Unrelated literal negations do not block collapsing
+        let output = merge(&[section("a", "/dist\n!/keep.txt\n"), section("b", "/dist\n")]);
+        assert_eq!(output.content, "# === a ===\n/dist\n!/keep.txt\n");
+    }
+
+    #[test]
+    fn reports_contradicting_templates() {
+        let output = merge(&[
+            section("docker", ".env*\n!.env.example\n"),
+            section("node", ".env.*\n"),
+            section("python", "!.env.example\n.env*\n"),
+        ]);
+        let conflicts: Vec<_> = output
+            .conflicts
+            .iter()
+            .map(|c| {
+                (
+                    c.template.as_str(),
+                    c.ignoring_template.as_str(),
+                    c.negation_wins,
+                )
+            })
+            .collect();
+        // python makes the same exception as docker, so only node conflicts
+        assert_eq!(
+            conflicts,
+            vec![("docker", "node", false), ("python", "node", true)]
+        );
+    }
+
+    #[test]
+    fn example_paths_satisfy_globs() {
+        assert_eq!(example_path("*.py[cod]"), "x.pyc");
+        assert_eq!(example_path("**/[Pp]ackages/build"), "xx/Packages/build");
+        assert_eq!(example_path("?*.[Cc]ache"), "xx.Cache");
+        assert_eq!(example_path("\\#file"), "#file");
+    }
+
+    #[test]
+    fn glob_negations_keep_later_copies() {
+        // `debug.log` is ignored again by b's `*.log`, which must stay last
+        let output = merge(&[section("a", "*.log\n!debug*\n"), section("b", "*.log\n")]);
+        assert_eq!(
+            output.content,
+            "# === a ===\n!debug*\n\n# === b ===\n*.log\n"
+        );
+
+        // A glob negation may overlap even if no example path shows it
+        let output = merge(&[section("a", "*.log\n!*.txt\n"), section("b", "*.log\n")]);
+        assert_eq!(
+            output.content,
+            "# === a ===\n!*.txt\n\n# === b ===\n*.log\n"
+        );
+
+        // Literal paths overlap when one contains the other
+        let output = merge(&[section("a", "build\n!src/build\n"), section("b", "build\n")]);
+        assert_eq!(
+            output.content,
+            "# === a ===\n!src/build\n\n# === b ===\nbuild\n"
+        );
+    }
+
+    #[test]
+    fn comments_are_not_deduplicated() {
+        let output = merge(&[
+            section("a", "# Build artifacts\n/target/\n"),
+            section("b", "# Build artifacts\n/dist/\n"),
+        ]);
+        assert_eq!(output.content.matches("# Build artifacts").count(), 2);
+    }
+}
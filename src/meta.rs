@@ -0,0 +1,139 @@
+//! Template front-matter.
+//!
+//! A template may start with a block of commented YAML describing it:
+//!
+//! ```text
+//! # ---
+//! # description: Rust build output and Cargo artifacts
+//! # aliases: [cargo]
+//! # tags: [lang]
+//! # detect: [Cargo.toml]
+//! # ---
+//! ```
+//!
+//! The block is stripped before templates are merged, so it never ends up in a
+//! generated `.gitignore`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Line that opens and closes a front-matter block
+const DELIMITER: &str = "---";
+
+/// Metadata declared in a template's front-matter
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemplateMeta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Other names the template can be requested by
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Free-form tags such as `lang`, `ide`, `os` or `tool`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Manifest globs that should make detection pick the template
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub detect: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Where the template content originally came from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+}
+
+/// Splits `content` into its front-matter and the remaining body.
+///
+/// Returns `None` for the metadata if the template has no front-matter block.
+pub fn split_front_matter(content: &str) -> Result<(Option<TemplateMeta>, &str)> {
+    let Some((yaml, body)) = front_matter_block(content) else {
+        return Ok((None, content));
+    };
+    let meta = if yaml.trim().is_empty() {
+        TemplateMeta::default()
+    } else {
+        serde_yaml::from_str(&yaml).context("parsing template front-matter")?
+    };
+    Ok((Some(meta), body))
+}
+
+/// Strips the front-matter from `content`, keeping only the template body
+pub fn body(content: &str) -> &str {
+    front_matter_block(content).map_or(content, |(_, body)| body)
+}
+
+/// Returns the uncommented YAML of the front-matter block and the body after it
+fn front_matter_block(content: &str) -> Option<(String, &str)> {
+    let mut lines = content.split_inclusive('\n');
+    let opening = lines.next()?;
+    if uncomment(opening)? != DELIMITER {
+        return None;
+    }
+
+    let mut yaml = String::new();
+    let mut consumed = opening.len();
+    for line in lines {
+        consumed += line.len();
+        let text = uncomment(line)?;
+        if text == DELIMITER {
+            return Some((yaml, &content[consumed..]));
+        }
+        yaml.push_str(text);
+        yaml.push('\n');
+    }
+    None
+}
+
+/// Strips the leading `#` (and one space) of a front-matter line
+fn uncomment(line: &str) -> Option<&str> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let rest = line.strip_prefix('#')?;
+    let rest = rest.strip_prefix(' ').unwrap_or(rest);
+    if rest.trim() == DELIMITER {
+        Some(DELIMITER)
+    } else {
+        Some(rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_front_matter() {
+        let content = "# ---\n# description: Node.js\n# aliases: [js, javascript]\n# tags: [lang]\n# detect: [package.json]\n# upstream: github/gitignore\n# ---\nnode_modules/\n";
+        let (meta, body) = split_front_matter(content).unwrap();
+        let meta = meta.unwrap();
+        assert_eq!(meta.description.as_deref(), Some("Node.js"));
+        assert_eq!(meta.aliases, vec!["js", "javascript"]);
+        assert_eq!(meta.tags, vec!["lang"]);
+        assert_eq!(meta.detect, vec!["package.json"]);
+        assert_eq!(meta.upstream.as_deref(), Some("github/gitignore"));
+        assert_eq!(body, "node_modules/\n");
+    }
+
+    #[test]
+    fn templates_without_front_matter_are_untouched() {
+        let content = "# Rust\n/target/\n";
+        assert_eq!(split_front_matter(content).unwrap(), (None, content));
+        // An unterminated block is treated as ordinary comments
+        let open = "# ---\n# description: x\n/target/\n";
+        assert_eq!(body(open), open);
+    }
+
+    #[test]
+    fn rejects_malformed_yaml() {
+        let content = "# ---\n# tags: [lang\n# ---\n";
+        assert!(split_front_matter(content).is_err());
+    }
+
+    #[test]
+    fn embedded_templates_have_valid_front_matter() {
+        for (name, content) in crate::templates::EMBEDDED_TEMPLATES {
+            let (meta, _) = split_front_matter(content).unwrap();
+            let meta = meta.unwrap_or_else(|| panic!("{name} has no front-matter"));
+            assert!(meta.description.is_some(), "{name} has no description");
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Project-local configuration (`.ignr.toml`).
+//!
+//! A project config uses the same keys as the global config and is layered on
+//! top of it, so a team can commit the settings that decide what `generate`
+//! writes. It is discovered by walking up from the scanned directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::warn;
+use toml::{Table, Value};
+
+/// File name of the project config
+pub const PROJECT_CONFIG_FILE: &str = ".ignr.toml";
+
+/// Finds the nearest `.ignr.toml` in `start` or one of its ancestors
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Reads a project config as TOML ready to be layered on the global config.
+///
+/// Relative paths are resolved against the directory containing the file and
+/// machine-specific `[paths]` settings are dropped.
+pub fn read_project_config(path: &Path) -> Result<String> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("reading project config {}", path.display()))?;
+    let mut table: Table = toml::from_str(&text)
+        .with_context(|| format!("parsing project config {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+
+    if table.remove("paths").is_some() {
+        warn!(
+            "Ignoring [paths] in project config {}; set it in the global config instead",
+            path.display()
+        );
+    }
+
+    if let Some(templates) = table.get_mut("templates").and_then(Value::as_table_mut) {
+        if let Some(Value::String(dir)) = templates.get_mut("template_dir") {
+            *dir = resolve_relative(base, dir);
+        }
+        if let Some(Value::Array(sources)) = templates.get_mut("sources") {
+            for source in sources.iter_mut().filter_map(Value::as_table_mut) {
+                let local = matches!(
+                    source.get("kind").and_then(Value::as_str),
+                    Some("dir" | "git-tarball")
+                );
+                if let Some(Value::String(location)) = source.get_mut("location")
+                    && local
+                    && !location.starts_with("http://")
+                    && !location.starts_with("https://")
+                {
+                    *location = resolve_relative(base, location);
+                }
+            }
+        }
+    }
+
+    if let Some(Value::Array(files)) = table
+        .get_mut("detection")
+        .and_then(Value::as_table_mut)
+        .and_then(|detection| detection.get_mut("rule_files"))
+    {
+        for file in files.iter_mut() {
+            if let Value::String(file) = file {
+                *file = resolve_relative(base, file);
+            }
+        }
+    }
+
+    toml::to_string(&table).context("serializing project config")
+}
+
+/// Anchors a relative path at `base`, leaving absolute and `~`/`$VAR` paths alone
+fn resolve_relative(base: &Path, path: &str) -> String {
+    if path.starts_with('~') || path.starts_with('$') || Path::new(path).is_absolute() {
+        path.to_string()
+    } else {
+        base.join(path).display().to_string()
+    }
+}
+
+/// Starter content for `ignr init --project`
+pub fn default_project_config() -> &'static str {
+    r#"# Project configuration for ignr
+# Commit this file so everyone generates the same .gitignore.
+# Keys match the global config and override it for this repository.
+
+[templates]
+# Templates to always include for this project
+# always_include = ["node", "docker"]
+
+# Project-specific patterns, written to a "custom" section
+# extra_patterns = ["/scratch/"]
+
+[detection]
+# Templates detection should never add here
+# exclude = ["cpp"]
+
+# Minimum score for a template to be detected
+# min_score = 1.0
+
+# Project-specific detection rules (relative to this file)
+# rule_files = ["ignr-rules.toml"]
+
+[header]
+# Leave the date out of the generated header
+# date = false
+"#
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_paths_are_anchored() {
+        let base = Path::new("/repo");
+        assert_eq!(resolve_relative(base, "rules.toml"), "/repo/rules.toml");
+        assert_eq!(resolve_relative(base, "/abs/rules.toml"), "/abs/rules.toml");
+        assert_eq!(resolve_relative(base, "~/rules.toml"), "~/rules.toml");
+        assert_eq!(resolve_relative(base, "$HOME/x"), "$HOME/x");
+    }
+
+    #[test]
+    fn discovers_config_in_ancestors_and_rewrites_paths() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("a/b");
+        fs::create_dir_all(&nested).unwrap();
+        let config = root.path().join(PROJECT_CONFIG_FILE);
+        fs::write(
+            &config,
+            "[templates]\ntemplate_dir = \"tpl\"\n\n[[templates.sources]]\nname = \"team\"\nkind = \"dir\"\nlocation = \"team\"\n\n[[templates.sources]]\nname = \"corp\"\nkind = \"http-api\"\nlocation = \"https://example.com/api\"\n\n[detection]\nrule_files = [\"rules.toml\"]\n\n[paths]\ndata_dir = \"/tmp\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(find_project_config(&nested), Some(config.clone()));
+
+        let table: Table = toml::from_str(&read_project_config(&config).unwrap()).unwrap();
+        let expected_dir = root.path().join("tpl").display().to_string();
+        assert_eq!(
+            table["templates"]["template_dir"].as_str(),
+            Some(expected_dir.as_str())
+        );
+        let sources = table["templates"]["sources"].as_array().unwrap();
+        let expected_team = root.path().join("team").display().to_string();
+        assert_eq!(
+            sources[0]["location"].as_str(),
+            Some(expected_team.as_str())
+        );
+        assert_eq!(
+            sources[1]["location"].as_str(),
+            Some("https://example.com/api")
+        );
+        assert!(!table.contains_key("paths"));
+    }
+}
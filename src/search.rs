@@ -0,0 +1,248 @@
+//! Ranked template search.
+//!
+//! Every query term is matched against template names, aliases, front-matter
+//! descriptions and tags, and the patterns of the template body. A pattern
+//! matches a term that is equal to it, contains it, or names a path the pattern
+//! would ignore, so `search foo.pyc` and `search '*.pyc'` both find the template
+//! containing `*.py[cod]`. Templates must match every term.
+
+use globset::Glob;
+use serde::Serialize;
+
+use crate::merge::example_path;
+use crate::templates::TemplateInfo;
+
+const NAME_EXACT: u32 = 100;
+const NAME_PARTIAL: u32 = 40;
+const ALIAS_EXACT: u32 = 80;
+const ALIAS_PARTIAL: u32 = 30;
+const DESCRIPTION: u32 = 15;
+const TAG: u32 = 10;
+const PATTERN_EXACT: u32 = 50;
+const PATTERN_GLOB: u32 = 35;
+const PATTERN_PARTIAL: u32 = 5;
+/// Pattern matches reported (and scored) per template and term
+const MAX_PATTERN_MATCHES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchField {
+    Name,
+    Alias,
+    Description,
+    Tag,
+    Pattern,
+}
+
+impl MatchField {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MatchField::Name => "name",
+            MatchField::Alias => "alias",
+            MatchField::Description => "description",
+            MatchField::Tag => "tag",
+            MatchField::Pattern => "pattern",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchMatch {
+    pub field: MatchField,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub name: String,
+    pub source: String,
+    pub score: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub matches: Vec<SearchMatch>,
+}
+
+/// Searches `templates` (paired with their body) for `terms`, best match first
+pub fn search(templates: &[(TemplateInfo, String)], terms: &[String]) -> Vec<SearchHit> {
+    let terms: Vec<String> = terms.iter().map(|t| t.to_lowercase()).collect();
+    let mut hits: Vec<SearchHit> = templates
+        .iter()
+        .filter_map(|(info, body)| score_template(info, body, &terms))
+        .collect();
+    hits.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+    hits
+}
+
+fn score_template(info: &TemplateInfo, body: &str, terms: &[String]) -> Option<SearchHit> {
+    let mut score = 0;
+    let mut matches = Vec::new();
+
+    for term in terms {
+        let term_score = score_term(info, body, term, &mut matches);
+        if term_score == 0 {
+            return None;
+        }
+        score += term_score;
+    }
+
+    Some(SearchHit {
+        name: info.name.clone(),
+        source: info.source.clone(),
+        score,
+        description: info.meta.description.clone(),
+        matches,
+    })
+}
+
+fn score_term(info: &TemplateInfo, body: &str, term: &str, matches: &mut Vec<SearchMatch>) -> u32 {
+    let mut score = 0;
+    let mut record = |field, text: &str, points| {
+        score += points;
+        let found = SearchMatch {
+            field,
+            text: text.to_string(),
+        };
+        if !matches.contains(&found) {
+            matches.push(found);
+        }
+    };
+
+    if info.name == term {
+        record(MatchField::Name, &info.name, NAME_EXACT);
+    } else if info.name.contains(term) {
+        record(MatchField::Name, &info.name, NAME_PARTIAL);
+    }
+
+    for alias in &info.meta.aliases {
+        let lower = alias.to_lowercase();
+        if lower == term {
+            record(MatchField::Alias, alias, ALIAS_EXACT);
+        } else if lower.contains(term) {
+            record(MatchField::Alias, alias, ALIAS_PARTIAL);
+        }
+    }
+
+    if let Some(ref description) = info.meta.description
+        && description.to_lowercase().contains(term)
+    {
+        record(MatchField::Description, description, DESCRIPTION);
+    }
+
+    for tag in &info.meta.tags {
+        if tag.to_lowercase() == term {
+            record(MatchField::Tag, tag, TAG);
+        }
+    }
+
+    let mut pattern_matches = 0;
+    for pattern in patterns(body) {
+        if pattern_matches == MAX_PATTERN_MATCHES {
+            break;
+        }
+        if let Some(points) = match_pattern(pattern, term) {
+            record(MatchField::Pattern, pattern, points);
+            pattern_matches += 1;
+        }
+    }
+
+    score
+}
+
+/// The non-comment, non-empty lines of a template body
+fn patterns(body: &str) -> impl Iterator<Item = &str> {
+    body.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Scores `term` against a single pattern
+fn match_pattern(pattern: &str, term: &str) -> Option<u32> {
+    let lower = pattern.to_lowercase();
+    let normalized = lower.trim_matches('/');
+    if lower == term || normalized == term.trim_matches('/') {
+        return Some(PATTERN_EXACT);
+    }
+    if pattern_ignores(normalized, &example_path(term)) {
+        return Some(PATTERN_GLOB);
+    }
+    lower.contains(term).then_some(PATTERN_PARTIAL)
+}
+
+/// Whether the gitignore pattern would ignore the path `term` (approximately)
+fn pattern_ignores(pattern: &str, term: &str) -> bool {
+    let pattern = pattern.trim_start_matches('!');
+    if !pattern.contains(['*', '?', '[']) {
+        return false;
+    }
+    let Ok(glob) = Glob::new(pattern) else {
+        return false;
+    };
+    let matcher = glob.compile_matcher();
+    let term = term.trim_matches('/');
+    // Unanchored patterns without a slash match the file name at any depth
+    matcher.is_match(term)
+        || (!pattern.contains('/') && term.rsplit('/').next().is_some_and(|n| matcher.is_match(n)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::TemplateMeta;
+
+    fn template(name: &str, aliases: &[&str], body: &str) -> (TemplateInfo, String) {
+        let meta = TemplateMeta {
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            ..TemplateMeta::default()
+        };
+        let info = TemplateInfo {
+            name: name.to_string(),
+            source: "embedded".to_string(),
+            meta,
+        };
+        (info, body.to_string())
+    }
+
+    fn names(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|h| h.name.as_str()).collect()
+    }
+
+    fn corpus() -> Vec<(TemplateInfo, String)> {
+        vec![
+            template("python", &["py"], "__pycache__/\n*.py[cod]\n"),
+            template("jupyter", &[], ".ipynb_checkpoints\n# *.pyc\n"),
+            template("node", &["js"], "node_modules/\n*.log\n"),
+        ]
+    }
+
+    #[test]
+    fn finds_templates_by_pattern() {
+        // A glob term matches patterns covering one of the paths it matches
+        assert_eq!(
+            names(&search(&corpus(), &["*.py[co]".to_string()])),
+            vec!["python"]
+        );
+        let hits = search(&corpus(), &["*.pyc".to_string()]);
+        assert_eq!(names(&hits), vec!["python"]);
+        assert!(hits[0].matches.contains(&SearchMatch {
+            field: MatchField::Pattern,
+            text: "*.py[cod]".to_string(),
+        }));
+    }
+
+    #[test]
+    fn paths_match_globs() {
+        let hits = search(&corpus(), &["src/app.log".to_string()]);
+        assert_eq!(names(&hits), vec!["node"]);
+        let hits = search(&corpus(), &["node_modules".to_string()]);
+        assert_eq!(names(&hits), vec!["node"]);
+    }
+
+    #[test]
+    fn names_rank_above_contents_and_all_terms_must_match() {
+        let hits = search(&corpus(), &["py".to_string()]);
+        assert_eq!(names(&hits), vec!["python", "jupyter"]);
+
+        let hits = search(&corpus(), &["js".to_string(), "pyc".to_string()]);
+        assert!(hits.is_empty());
+    }
+}
@@ -0,0 +1,1022 @@
+//! Fetching templates from a gitignore.io-compatible API.
+//!
+//! The API lists template names at `<url>/list` and serves each template at
+//! `<url>/<name>`. Templates are fetched by a bounded pool of worker threads,
+//! and transient failures (timeouts, connection errors, HTTP 429 and 5xx) are
+//! retried with exponential backoff.
+//!
+//! The `ETag` and `Last-Modified` of every response are cached, so the next
+//! sync makes conditional requests and only downloads templates that changed.
+//!
+//! Every template written is appended to a journal. If a sync is interrupted
+//! or some templates fail, the next sync from the same URL skips the templates
+//! already in the journal; the journal is removed once a sync completes.
+//!
+//! [`SyncLayout`] stages downloads outside the data directory and only swaps
+//! them in once the sync succeeded, keeping the replaced set for rollback.
+//! Template sets read by other means, such as [`crate::github`], are installed
+//! the same way.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use log::{debug, warn};
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+
+use crate::lock::content_hash;
+
+/// File recording the templates written by an unfinished sync
+const JOURNAL_FILE: &str = "sync.journal";
+const JOURNAL_URL_PREFIX: &str = "url ";
+/// File below `cache_dir` holding the validators of the last responses
+const HTTP_CACHE_FILE: &str = "http-cache.json";
+
+/// Directory below `cache_dir` holding the staged downloads and the journal
+const STAGING_DIR: &str = "sync";
+/// Directory holding the synced templates, below `data_dir`
+pub const TEMPLATES_DIR: &str = "templates";
+const PREVIOUS_DIR: &str = "templates.previous";
+/// Temporary names inside `data_dir` used while swapping template sets
+const INCOMING_DIR: &str = ".templates.incoming";
+const SWAP_DIR: &str = ".templates.swap";
+
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// Number of templates fetched concurrently
+    pub jobs: usize,
+    /// Retries per request after the first attempt
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each further retry
+    pub backoff: Duration,
+    pub timeout: Duration,
+    /// Skip templates recorded in the journal of an unfinished sync
+    pub resume: bool,
+    /// Show a progress line on stderr
+    pub progress: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            jobs: 8,
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(30),
+            resume: true,
+            progress: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SyncReport {
+    /// Templates listed by the remote
+    pub total: usize,
+    /// Templates that are new or whose content changed
+    pub updated: usize,
+    /// Templates the remote reported (or served) unchanged
+    pub unchanged: usize,
+    /// Templates skipped because an earlier, unfinished sync already fetched them
+    pub resumed: usize,
+    pub failed: Vec<String>,
+    /// Whether the synced templates replaced the templates in use
+    pub installed: bool,
+}
+
+/// Validators and content hash of the last response for each template
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HttpCache {
+    url: String,
+    templates: BTreeMap<String, CacheEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    /// SHA-256 of the content the validators belong to
+    sha256: String,
+}
+
+impl HttpCache {
+    /// Reads the cache for `url`; a missing, unreadable or foreign cache is empty
+    fn load(path: &Path, url: &str) -> Self {
+        let cache = fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<HttpCache>(&text).ok())
+            .filter(|cache| cache.url == url);
+        cache.unwrap_or_else(|| HttpCache {
+            url: url.to_string(),
+            templates: BTreeMap::new(),
+        })
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self).context("serializing HTTP cache")?;
+        fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+    }
+}
+
+/// What fetching a single template did
+enum Outcome {
+    Updated,
+    Unchanged,
+}
+
+/// The directories a sync reads and writes
+#[derive(Debug, Clone)]
+pub struct SyncLayout {
+    /// Templates in use (`data_dir/templates`)
+    pub live: PathBuf,
+    /// Templates replaced by the last sync, restored by [`SyncLayout::rollback`]
+    pub previous: PathBuf,
+    /// Downloads of the current sync, below `cache_dir`
+    staging: PathBuf,
+    journal: PathBuf,
+    http_cache: PathBuf,
+    incoming: PathBuf,
+    swap: PathBuf,
+}
+
+impl SyncLayout {
+    pub fn new(data_dir: &Path, cache_dir: &Path) -> Self {
+        let stage = cache_dir.join(STAGING_DIR);
+        Self {
+            live: data_dir.join(TEMPLATES_DIR),
+            previous: data_dir.join(PREVIOUS_DIR),
+            staging: stage.join(TEMPLATES_DIR),
+            journal: stage.join(JOURNAL_FILE),
+            http_cache: cache_dir.join(HTTP_CACHE_FILE),
+            incoming: data_dir.join(INCOMING_DIR),
+            swap: data_dir.join(SWAP_DIR),
+        }
+    }
+
+    /// Syncs from `url` into the staging directory and installs the result if
+    /// every template was fetched (or `allow_failures` is set).
+    ///
+    /// The templates in use are never modified before the install, which
+    /// replaces them as a whole. Nothing is installed if nothing changed.
+    pub fn sync(
+        &self,
+        url: &str,
+        options: &SyncOptions,
+        allow_failures: bool,
+    ) -> Result<SyncReport> {
+        let url = url.trim_end_matches('/');
+        self.recover()?;
+        let resumable = options.resume && !read_journal(&self.journal, url)?.is_empty();
+        if !resumable {
+            remove_dir_if_exists(&self.staging)?;
+        }
+        fs::create_dir_all(&self.staging)
+            .with_context(|| format!("creating {}", self.staging.display()))?;
+
+        let report = self.fetch(url, options)?;
+        self.finish(report, allow_failures)
+    }
+
+    /// Installs `templates` (keyed by name), replacing all synced templates
+    pub fn sync_templates(&self, templates: &BTreeMap<String, String>) -> Result<SyncReport> {
+        self.recover()?;
+        // The staging directory is rebuilt, so an unfinished sync cannot resume
+        if self.journal.exists() {
+            fs::remove_file(&self.journal)
+                .with_context(|| format!("removing {}", self.journal.display()))?;
+        }
+        remove_dir_if_exists(&self.staging)?;
+        fs::create_dir_all(&self.staging)
+            .with_context(|| format!("creating {}", self.staging.display()))?;
+
+        let mut report = SyncReport {
+            total: templates.len(),
+            ..SyncReport::default()
+        };
+        for (name, content) in templates {
+            let file = format!("{name}.gitignore");
+            let live = self.live.join(&file);
+            let staged = self.staging.join(&file);
+            if fs::read_to_string(&live).is_ok_and(|current| current == *content) {
+                link_or_copy(&live, &staged)?;
+                report.unchanged += 1;
+            } else {
+                replace_file(&staged, content)?;
+                report.updated += 1;
+            }
+        }
+        self.finish(report, false)
+    }
+
+    /// Installs the staged templates unless nothing changed or templates
+    /// failed (and `allow_failures` is not set).
+    ///
+    /// Templates that failed keep their installed copy.
+    fn finish(&self, mut report: SyncReport, allow_failures: bool) -> Result<SyncReport> {
+        let unchanged = report.updated == 0
+            && report.resumed == 0
+            && report.failed.is_empty()
+            && file_names(&self.staging)? == file_names(&self.live)?;
+        if unchanged {
+            debug!("Templates are up to date, keeping {}", self.live.display());
+            remove_dir_if_exists(&self.staging)?;
+        } else if report.failed.is_empty() || allow_failures {
+            for name in &report.failed {
+                let file = format!("{name}.gitignore");
+                let live = self.live.join(&file);
+                if live.exists() {
+                    link_or_copy(&live, &self.staging.join(&file))?;
+                }
+            }
+            self.install()?;
+            report.installed = true;
+        }
+        Ok(report)
+    }
+
+    /// Fetches every template listed at `url` into the staging directory.
+    ///
+    /// Fails only if the template list cannot be fetched; failures of
+    /// individual templates are reported.
+    fn fetch(&self, url: &str, options: &SyncOptions) -> Result<SyncReport> {
+        let client = Client::builder()
+            .timeout(options.timeout)
+            .build()
+            .context("building HTTP client")?;
+
+        let list_url = format!("{url}/list");
+        debug!("Fetching template list from {list_url}");
+        let list = match get_with_retry(&client, &list_url, None, options)
+            .context("fetching template list")?
+        {
+            Response::Modified { body, .. } => text(body)?,
+            Response::NotModified => return Err(anyhow!("unexpected 304 for {list_url}")),
+        };
+        let names = parse_list(&list);
+
+        let done = if options.resume {
+            read_journal(&self.journal, url)?
+        } else {
+            BTreeSet::new()
+        };
+        let journal = Mutex::new(open_journal(&self.journal, url, !done.is_empty())?);
+        let pending: Vec<&String> = names.iter().filter(|n| !done.contains(*n)).collect();
+        let resumed = names.len() - pending.len();
+        if resumed > 0 {
+            debug!("Resuming sync, {resumed} templates already synced");
+        }
+
+        let cache = HttpCache::load(&self.http_cache, url);
+        let entries = Mutex::new(BTreeMap::new());
+        let progress = Progress::new(options.progress, names.len(), resumed);
+        let next = AtomicUsize::new(0);
+        let failed = Mutex::new(Vec::new());
+        let updated = AtomicUsize::new(0);
+        let unchanged = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..options.jobs.clamp(1, pending.len().max(1)) {
+                scope.spawn(|| {
+                    while let Some(name) = pending.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let cached = cache.templates.get(*name);
+                        match self.fetch_template(&client, url, name, cached, options) {
+                            Ok((outcome, entry)) => {
+                                let mut journal = journal.lock().unwrap_or_else(|e| e.into_inner());
+                                if let Err(e) = writeln!(journal, "{name}") {
+                                    debug!("Failed to update sync journal: {e}");
+                                }
+                                let counter = match outcome {
+                                    Outcome::Updated => &updated,
+                                    Outcome::Unchanged => &unchanged,
+                                };
+                                counter.fetch_add(1, Ordering::Relaxed);
+                                entries
+                                    .lock()
+                                    .unwrap_or_else(|e| e.into_inner())
+                                    .insert(name.to_string(), entry);
+                            }
+                            Err(e) => {
+                                warn!("Failed to sync '{name}': {e:#}");
+                                failed
+                                    .lock()
+                                    .unwrap_or_else(|e| e.into_inner())
+                                    .push(name.to_string());
+                            }
+                        }
+                        progress.tick(name);
+                    }
+                });
+            }
+        });
+        progress.finish();
+
+        // Keep the old validators of templates not fetched by this run
+        let mut entries = entries.into_inner().unwrap_or_else(|e| e.into_inner());
+        let cache = HttpCache {
+            url: url.to_string(),
+            templates: names
+                .iter()
+                .filter_map(|name| {
+                    let entry = entries
+                        .remove(name)
+                        .or_else(|| cache.templates.get(name).cloned())?;
+                    Some((name.clone(), entry))
+                })
+                .collect(),
+        };
+        cache.save(&self.http_cache)?;
+
+        let mut failed = failed.into_inner().unwrap_or_else(|e| e.into_inner());
+        failed.sort();
+        if failed.is_empty() {
+            drop(journal);
+            fs::remove_file(&self.journal)
+                .with_context(|| format!("removing {}", self.journal.display()))?;
+        }
+
+        Ok(SyncReport {
+            total: names.len(),
+            updated: updated.into_inner(),
+            unchanged: unchanged.into_inner(),
+            resumed,
+            failed,
+            installed: false,
+        })
+    }
+
+    /// Fetches one template into the staging directory, conditionally if the
+    /// template in use still has the content the cached validators belong to
+    fn fetch_template(
+        &self,
+        client: &Client,
+        url: &str,
+        name: &str,
+        cached: Option<&CacheEntry>,
+        options: &SyncOptions,
+    ) -> Result<(Outcome, CacheEntry)> {
+        let file = format!("{name}.gitignore");
+        let live = self.live.join(&file);
+        let staged = self.staging.join(&file);
+        let current = fs::read_to_string(&live).ok();
+        let cached = cached.filter(|entry| {
+            current
+                .as_deref()
+                .is_some_and(|c| content_hash(c) == entry.sha256)
+        });
+
+        match get_with_retry(client, &format!("{url}/{name}"), cached, options)? {
+            Response::NotModified => {
+                let entry = cached.cloned().ok_or_else(|| anyhow!("unexpected 304"))?;
+                link_or_copy(&live, &staged)?;
+                Ok((Outcome::Unchanged, entry))
+            }
+            Response::Modified {
+                body,
+                etag,
+                last_modified,
+            } => {
+                let content = text(body)?;
+                let entry = CacheEntry {
+                    etag,
+                    last_modified,
+                    sha256: content_hash(&content),
+                };
+                if current.as_deref() == Some(content.as_str()) {
+                    link_or_copy(&live, &staged)?;
+                    Ok((Outcome::Unchanged, entry))
+                } else {
+                    replace_file(&staged, &content)?;
+                    Ok((Outcome::Updated, entry))
+                }
+            }
+        }
+    }
+
+    /// Replaces the templates in use with the staged ones, keeping the
+    /// replaced set as the previous snapshot
+    fn install(&self) -> Result<()> {
+        remove_dir_if_exists(&self.incoming)?;
+        move_dir(&self.staging, &self.incoming)?;
+        remove_dir_if_exists(&self.previous)?;
+        if self.live.exists() {
+            rename(&self.live, &self.previous)?;
+        }
+        rename(&self.incoming, &self.live)?;
+        if self.journal.exists() {
+            fs::remove_file(&self.journal)
+                .with_context(|| format!("removing {}", self.journal.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Swaps the templates in use with the previous snapshot, so rolling back
+    /// twice restores the synced set
+    pub fn rollback(&self) -> Result<()> {
+        self.recover()?;
+        if !self.previous.exists() {
+            return Err(anyhow!(
+                "No previous templates to roll back to ({} does not exist)",
+                self.previous.display()
+            ));
+        }
+        if self.live.exists() {
+            remove_dir_if_exists(&self.swap)?;
+            rename(&self.live, &self.swap)?;
+            rename(&self.previous, &self.live)?;
+            rename(&self.swap, &self.previous)
+        } else {
+            rename(&self.previous, &self.live)
+        }
+    }
+
+    /// Finishes a swap interrupted between its renames
+    pub fn recover(&self) -> Result<()> {
+        for pending in [&self.incoming, &self.swap] {
+            if !pending.exists() {
+                continue;
+            }
+            if self.live.exists() {
+                remove_dir_if_exists(pending)?;
+            } else {
+                warn!(
+                    "Restoring templates from interrupted swap {}",
+                    pending.display()
+                );
+                rename(pending, &self.live)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn rename(from: &Path, to: &Path) -> Result<()> {
+    fs::rename(from, to).with_context(|| format!("moving {} to {}", from.display(), to.display()))
+}
+
+fn remove_dir_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_dir_all(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("removing {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Writes `content` to a new file at `path`, never through an existing hard link
+fn replace_file(path: &Path, content: &str) -> Result<()> {
+    let _ = fs::remove_file(path);
+    fs::write(path, content).with_context(|| format!("writing {}", path.display()))
+}
+
+/// Hard-links `from` to `to`, copying if linking is not possible
+fn link_or_copy(from: &Path, to: &Path) -> Result<()> {
+    let _ = fs::remove_file(to);
+    if fs::hard_link(from, to).is_err() {
+        fs::copy(from, to).with_context(|| format!("copying {}", from.display()))?;
+    }
+    Ok(())
+}
+
+/// Moves a flat directory of files, copying if `to` is on another file system
+fn move_dir(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::create_dir_all(to).with_context(|| format!("creating {}", to.display()))?;
+    for entry in fs::read_dir(from).with_context(|| format!("reading {}", from.display()))? {
+        let entry = entry?;
+        fs::copy(entry.path(), to.join(entry.file_name()))
+            .with_context(|| format!("copying {}", entry.path().display()))?;
+    }
+    remove_dir_if_exists(from)
+}
+
+/// Names of the files in `dir` (empty if it does not exist)
+fn file_names(dir: &Path) -> Result<BTreeSet<OsString>> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .map(|entry| Ok(entry?.file_name()))
+            .collect::<io::Result<_>>()
+            .with_context(|| format!("reading {}", dir.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeSet::new()),
+        Err(e) => Err(e).with_context(|| format!("reading {}", dir.display())),
+    }
+}
+
+/// Template names from a `/list` response (comma and/or newline separated)
+fn parse_list(list: &str) -> Vec<String> {
+    let names: BTreeSet<String> = list
+        .split([',', '\n'])
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty() && !name.contains(['/', '\\']) && name != "..")
+        .collect();
+    names.into_iter().collect()
+}
+
+/// Templates recorded in the journal of an unfinished sync from `url`
+fn read_journal(path: &Path, url: &str) -> Result<BTreeSet<String>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeSet::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    let mut lines = text.lines();
+    if lines
+        .next()
+        .and_then(|l| l.strip_prefix(JOURNAL_URL_PREFIX))
+        != Some(url)
+    {
+        debug!("Ignoring sync journal from a different URL");
+        return Ok(BTreeSet::new());
+    }
+    Ok(lines.map(str::to_string).collect())
+}
+
+/// Opens the journal for appending, starting a new one unless `resume` is set
+fn open_journal(path: &Path, url: &str, resume: bool) -> Result<File> {
+    let context = || format!("opening {}", path.display());
+    if resume {
+        return OpenOptions::new()
+            .append(true)
+            .open(path)
+            .with_context(context);
+    }
+    let mut file = File::create(path).with_context(context)?;
+    writeln!(file, "{JOURNAL_URL_PREFIX}{url}").with_context(context)?;
+    Ok(file)
+}
+
+/// Downloads `url`, retrying transient failures
+pub fn download(url: &str, options: &SyncOptions) -> Result<Vec<u8>> {
+    let client = Client::builder()
+        .timeout(options.timeout)
+        .build()
+        .context("building HTTP client")?;
+    debug!("Downloading {url}");
+    match get_with_retry(&client, url, None, options).with_context(|| format!("fetching {url}"))? {
+        Response::Modified { body, .. } => Ok(body),
+        Response::NotModified => Err(anyhow!("unexpected 304 for {url}")),
+    }
+}
+
+fn text(body: Vec<u8>) -> Result<String> {
+    String::from_utf8(body).context("response is not valid UTF-8")
+}
+
+enum Response {
+    Modified {
+        body: Vec<u8>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+    NotModified,
+}
+
+/// A failed request, and whether it is worth retrying
+struct FetchError {
+    error: anyhow::Error,
+    retryable: bool,
+}
+
+fn get_with_retry(
+    client: &Client,
+    url: &str,
+    cached: Option<&CacheEntry>,
+    options: &SyncOptions,
+) -> Result<Response> {
+    let mut attempt = 0;
+    loop {
+        match get(client, url, cached) {
+            Ok(response) => return Ok(response),
+            Err(e) if e.retryable && attempt < options.retries => {
+                let delay = options.backoff * 2u32.pow(attempt);
+                debug!("{url}: {:#}, retrying in {delay:?}", e.error);
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(e) => return Err(e.error),
+        }
+    }
+}
+
+/// GETs `url`, conditionally on the validators in `cached`
+fn get(client: &Client, url: &str, cached: Option<&CacheEntry>) -> Result<Response, FetchError> {
+    let mut request = client.get(url);
+    if let Some(entry) = cached {
+        if let Some(ref etag) = entry.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(ref last_modified) = entry.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = request.send().map_err(|e| FetchError {
+        retryable: e.is_timeout() || e.is_connect() || e.is_request(),
+        error: e.into(),
+    })?;
+
+    let status = response.status();
+    if status == StatusCode::NOT_MODIFIED {
+        return Ok(Response::NotModified);
+    }
+    if !status.is_success() {
+        return Err(FetchError {
+            error: anyhow!("HTTP {status}"),
+            retryable: status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+        });
+    }
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let body = response.bytes().map_err(|e| FetchError {
+        error: e.into(),
+        retryable: true,
+    })?;
+    Ok(Response::Modified {
+        body: body.to_vec(),
+        etag,
+        last_modified,
+    })
+}
+
+/// A single, self-overwriting progress line on stderr
+struct Progress {
+    enabled: bool,
+    total: usize,
+    done: AtomicUsize,
+}
+
+impl Progress {
+    fn new(enabled: bool, total: usize, done: usize) -> Self {
+        Self {
+            enabled: enabled && io::stderr().is_terminal(),
+            total,
+            done: AtomicUsize::new(done),
+        }
+    }
+
+    fn tick(&self, name: &str) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.enabled {
+            eprint!("\r\x1b[2KSyncing [{done}/{}] {name}", self.total);
+        }
+    }
+
+    fn finish(&self) {
+        if self.enabled {
+            eprint!("\r\x1b[2K");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    /// A request received by the mock server
+    struct Request {
+        path: String,
+        /// How many requests for `path` were received, including this one
+        nth: usize,
+        headers: Vec<(String, String)>,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    struct Reply {
+        status: u16,
+        headers: Vec<String>,
+        body: String,
+    }
+
+    fn reply(status: u16, body: &str) -> Reply {
+        Reply {
+            status,
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+
+    type Handler = dyn Fn(&Request) -> Reply + Send + Sync;
+    type Hits = Arc<Mutex<HashMap<String, usize>>>;
+
+    /// A minimal HTTP server answering every request with `handler`; returns
+    /// its URL and the number of requests per path
+    fn serve(handler: Arc<Handler>) -> (String, Hits) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits: Hits = Arc::default();
+        let counts = Arc::clone(&hits);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let handler = Arc::clone(&handler);
+                let counts = Arc::clone(&counts);
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut request_line = String::new();
+                    reader.read_line(&mut request_line).unwrap();
+                    let mut headers = Vec::new();
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        let Some((name, value)) = line.trim_end().split_once(':') else {
+                            break;
+                        };
+                        headers.push((name.to_string(), value.trim().to_string()));
+                    }
+                    let path = request_line.split(' ').nth(1).unwrap_or("/").to_string();
+                    let nth = {
+                        let mut counts = counts.lock().unwrap();
+                        let count = counts.entry(path.clone()).or_insert(0);
+                        *count += 1;
+                        *count
+                    };
+                    let reply = handler(&Request { path, nth, headers });
+                    let mut head = format!(
+                        "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        reply.status,
+                        reply.body.len()
+                    );
+                    for header in &reply.headers {
+                        head.push_str(&format!("{header}\r\n"));
+                    }
+                    let _ = write!(stream, "{head}\r\n{}", reply.body);
+                });
+            }
+        });
+        (url, hits)
+    }
+
+    fn options() -> SyncOptions {
+        SyncOptions {
+            jobs: 4,
+            backoff: Duration::from_millis(1),
+            ..SyncOptions::default()
+        }
+    }
+
+    fn read(dir: &Path, name: &str) -> Option<String> {
+        fs::read_to_string(dir.join(format!("{name}.gitignore"))).ok()
+    }
+
+    #[test]
+    fn parses_comma_and_newline_separated_lists() {
+        assert_eq!(
+            parse_list("Rust,node\npython,\n../etc,rust\n"),
+            vec!["node", "python", "rust"]
+        );
+    }
+
+    #[test]
+    fn retries_and_resumes_without_touching_installed_templates() {
+        let (url, hits) = serve(Arc::new(|req: &Request| match req.path.as_str() {
+            "/list" => reply(200, "rust,node,python\nflaky,missing"),
+            // Fails twice, then recovers
+            "/flaky" if req.nth <= 2 => reply(503, ""),
+            "/missing" => reply(404, ""),
+            path => reply(200, &format!("{path} v{}\n", req.nth)),
+        }));
+        let data = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let layout = SyncLayout::new(data.path(), cache.path());
+
+        let report = layout.sync(&url, &options(), false).unwrap();
+        assert_eq!(report.total, 5);
+        assert_eq!((report.updated, report.unchanged), (4, 0));
+        assert_eq!(report.failed, vec!["missing"]);
+        assert!(!report.installed);
+        assert_eq!(
+            read(&layout.staging, "flaky").as_deref(),
+            Some("/flaky v3\n")
+        );
+        assert!(!layout.live.exists());
+        // 404 is not retried
+        assert_eq!(hits.lock().unwrap()["/missing"], 1);
+
+        // The next run only fetches what is still missing
+        let report = layout.sync(&url, &options(), true).unwrap();
+        assert_eq!((report.updated, report.resumed), (0, 4));
+        assert!(report.installed);
+        assert_eq!(read(&layout.live, "rust").as_deref(), Some("/rust v1\n"));
+        assert!(!layout.journal.exists());
+        let hits = hits.lock().unwrap();
+        assert_eq!(hits["/rust"], 1);
+        assert_eq!(hits["/missing"], 2);
+    }
+
+    #[test]
+    fn allowed_failures_keep_the_installed_copy() {
+        let (url, _) = serve(Arc::new(|req: &Request| match req.path.as_str() {
+            "/list" => reply(200, "rust,node"),
+            "/node" if req.nth > 1 => reply(503, ""),
+            path => reply(200, &format!("{path} v{}\n", req.nth)),
+        }));
+        let data = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let layout = SyncLayout::new(data.path(), cache.path());
+        assert!(layout.sync(&url, &options(), false).unwrap().installed);
+
+        let report = layout.sync(&url, &options(), true).unwrap();
+        assert_eq!(report.failed, vec!["node"]);
+        assert!(report.installed);
+        assert_eq!(read(&layout.live, "rust").as_deref(), Some("/rust v2\n"));
+        assert_eq!(read(&layout.live, "node").as_deref(), Some("/node v1\n"));
+    }
+
+    #[test]
+    fn conditional_requests_skip_unchanged_templates() {
+        let (url, hits) = serve(Arc::new(|req: &Request| match req.path.as_str() {
+            "/list" => reply(200, "rust,node,plain"),
+            // Supports ETags; changes once, on the third request
+            "/rust" => {
+                let etag = if req.nth < 3 { "\"v1\"" } else { "\"v2\"" };
+                if req.header("if-none-match") == Some(etag) {
+                    return reply(304, "");
+                }
+                Reply {
+                    headers: vec![format!("ETag: {etag}")],
+                    ..reply(200, &format!("rust {etag}\n"))
+                }
+            }
+            // Supports Last-Modified
+            "/node" => match req.header("if-modified-since") {
+                Some(_) => reply(304, ""),
+                None => Reply {
+                    headers: vec!["Last-Modified: Wed, 21 Oct 2015 07:28:00 GMT".to_string()],
+                    ..reply(200, "node\n")
+                },
+            },
+            // No validators, same content every time
+            _ => reply(200, "plain\n"),
+        }));
+        let data = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let layout = SyncLayout::new(data.path(), cache.path());
+
+        let report = layout.sync(&url, &options(), false).unwrap();
+        assert_eq!((report.updated, report.unchanged), (3, 0));
+        assert!(report.installed);
+
+        // Nothing changed: nothing is installed, so the snapshot is kept
+        let report = layout.sync(&url, &options(), false).unwrap();
+        assert_eq!((report.updated, report.unchanged), (0, 3));
+        assert!(!report.installed);
+        assert!(!layout.previous.exists());
+
+        let report = layout.sync(&url, &options(), false).unwrap();
+        assert_eq!((report.updated, report.unchanged), (1, 2));
+        assert!(report.installed);
+        assert_eq!(read(&layout.live, "rust").as_deref(), Some("rust \"v2\"\n"));
+        assert_eq!(read(&layout.live, "node").as_deref(), Some("node\n"));
+        assert_eq!(
+            read(&layout.previous, "rust").as_deref(),
+            Some("rust \"v1\"\n")
+        );
+
+        // After a rollback the cached validators no longer describe the
+        // installed templates, so they are fetched in full
+        layout.rollback().unwrap();
+        let report = layout.sync(&url, &options(), false).unwrap();
+        assert_eq!((report.updated, report.unchanged), (1, 2));
+        assert_eq!(read(&layout.live, "rust").as_deref(), Some("rust \"v2\"\n"));
+        assert_eq!(hits.lock().unwrap()["/node"], 4);
+    }
+
+    #[test]
+    fn installs_atomically_and_rolls_back() {
+        let (url, _) = serve(Arc::new(|req: &Request| match req.path.as_str() {
+            "/list" => reply(200, "rust,node"),
+            // Unavailable during the second sync
+            "/node" if req.nth == 2 => reply(404, ""),
+            path => reply(200, &format!("{path} v{}\n", req.nth)),
+        }));
+        let data = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let layout = SyncLayout::new(data.path(), cache.path());
+
+        let report = layout.sync(&url, &options(), false).unwrap();
+        assert!(report.installed);
+        assert_eq!(read(&layout.live, "rust").as_deref(), Some("/rust v1\n"));
+        assert!(!layout.previous.exists());
+
+        // A failing sync leaves the templates in use alone
+        let report = layout.sync(&url, &options(), false).unwrap();
+        assert!(!report.installed);
+        assert_eq!(read(&layout.live, "rust").as_deref(), Some("/rust v1\n"));
+
+        // Resuming fetches only node, then installs the complete set
+        let report = layout.sync(&url, &options(), false).unwrap();
+        assert!(report.installed);
+        assert_eq!((report.updated, report.resumed), (1, 1));
+        assert_eq!(read(&layout.live, "rust").as_deref(), Some("/rust v2\n"));
+        assert_eq!(read(&layout.live, "node").as_deref(), Some("/node v3\n"));
+        assert_eq!(
+            read(&layout.previous, "rust").as_deref(),
+            Some("/rust v1\n")
+        );
+
+        layout.rollback().unwrap();
+        assert_eq!(read(&layout.live, "rust").as_deref(), Some("/rust v1\n"));
+        layout.rollback().unwrap();
+        assert_eq!(read(&layout.live, "rust").as_deref(), Some("/rust v2\n"));
+    }
+
+    #[test]
+    fn restarts_when_the_journal_is_from_another_url() {
+        let (url, _) = serve(Arc::new(|req: &Request| match req.path.as_str() {
+            "/list" => reply(200, "rust,node"),
+            _ => reply(200, "x\n"),
+        }));
+        let data = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let layout = SyncLayout::new(data.path(), cache.path());
+        fs::create_dir_all(&layout.staging).unwrap();
+        fs::write(layout.staging.join("stale.gitignore"), "x\n").unwrap();
+        fs::write(&layout.journal, "url http://elsewhere\nrust\nstale\n").unwrap();
+
+        let report = layout.sync(&url, &options(), false).unwrap();
+        assert_eq!((report.updated, report.resumed), (2, 0));
+        assert!(report.failed.is_empty());
+        assert!(read(&layout.live, "stale").is_none());
+        assert!(!layout.journal.exists());
+    }
+
+    #[test]
+    fn installs_template_sets() {
+        let data = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let layout = SyncLayout::new(data.path(), cache.path());
+        let templates = |python: &str| {
+            BTreeMap::from([
+                ("python".to_string(), python.to_string()),
+                ("rust".to_string(), "/target/\n".to_string()),
+            ])
+        };
+
+        let report = layout.sync_templates(&templates("v1\n")).unwrap();
+        assert_eq!((report.updated, report.unchanged), (2, 0));
+        assert!(report.installed);
+
+        let report = layout.sync_templates(&templates("v1\n")).unwrap();
+        assert_eq!((report.updated, report.unchanged), (0, 2));
+        assert!(!report.installed);
+
+        let report = layout.sync_templates(&templates("v2\n")).unwrap();
+        assert_eq!((report.updated, report.unchanged), (1, 1));
+        assert_eq!(read(&layout.live, "python").as_deref(), Some("v2\n"));
+        assert_eq!(read(&layout.previous, "python").as_deref(), Some("v1\n"));
+    }
+
+    #[test]
+    fn recovers_from_interrupted_swap() {
+        let data = tempfile::tempdir().unwrap();
+        let layout = SyncLayout::new(data.path(), data.path());
+        assert!(layout.rollback().is_err());
+
+        fs::create_dir_all(&layout.incoming).unwrap();
+        fs::write(layout.incoming.join("rust.gitignore"), "x\n").unwrap();
+        fs::create_dir_all(&layout.previous).unwrap();
+        layout.recover().unwrap();
+        assert_eq!(read(&layout.live, "rust").as_deref(), Some("x\n"));
+        assert!(!layout.incoming.exists());
+    }
+
+    #[test]
+    fn failing_list_is_an_error() {
+        let (url, hits) = serve(Arc::new(|_: &Request| reply(500, "")));
+        let data = tempfile::tempdir().unwrap();
+        let layout = SyncLayout::new(data.path(), data.path());
+        assert!(layout.sync(&url, &options(), false).is_err());
+        assert_eq!(hits.lock().unwrap()["/list"], 4);
+    }
+}
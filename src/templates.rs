@@ -0,0 +1,1090 @@
+//! Template lookup and merging.
+//!
+//! Templates are resolved from the sources in `[[templates.sources]]`, highest
+//! priority first: directories, gitignore.io-compatible APIs and
+//! github/gitignore-style repositories (both downloaded by `sync`), and the
+//! templates embedded in the binary. A source with a namespace can be
+//! addressed directly as `namespace/template`.
+//!
+//! Without configured sources the legacy layers are used: the custom
+//! `template_dir`, the data directory populated by `sync` from `template_url`,
+//! and the embedded templates. `prefer_local` decides whether the custom
+//! directory is consulted first or last.
+//!
+//! A template can pull in other templates with an include directive, e.g.
+//! `#!include linux macos`. Includes are expanded in place, recursively.
+//! A template including its own name extends the copy it shadows, i.e. the
+//! one from the next lower-priority source.
+//! Front-matter metadata (see [`crate::meta`]) is stripped before merging.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::lock::{self, LOCK_FILE, Lockfile};
+use crate::merge::{self, Section};
+use crate::meta::{self, TemplateMeta};
+use crate::sync::{self, SyncLayout};
+use crate::{AppConfig, SourceConfig, expand_str_path};
+
+/// Embedded templates from the templates/ directory
+pub const EMBEDDED_TEMPLATES: &[(&str, &str)] = &[
+    ("rust", include_str!("../templates/rust.gitignore")),
+    ("python", include_str!("../templates/python.gitignore")),
+    ("node", include_str!("../templates/node.gitignore")),
+    ("go", include_str!("../templates/go.gitignore")),
+    ("java", include_str!("../templates/java.gitignore")),
+    ("csharp", include_str!("../templates/csharp.gitignore")),
+    ("cpp", include_str!("../templates/cpp.gitignore")),
+    ("ruby", include_str!("../templates/ruby.gitignore")),
+    ("swift", include_str!("../templates/swift.gitignore")),
+    ("kotlin", include_str!("../templates/kotlin.gitignore")),
+    ("php", include_str!("../templates/php.gitignore")),
+    ("scala", include_str!("../templates/scala.gitignore")),
+    ("elixir", include_str!("../templates/elixir.gitignore")),
+    ("haskell", include_str!("../templates/haskell.gitignore")),
+    ("zig", include_str!("../templates/zig.gitignore")),
+    ("dart", include_str!("../templates/dart.gitignore")),
+    (
+        "terraform",
+        include_str!("../templates/terraform.gitignore"),
+    ),
+    ("ansible", include_str!("../templates/ansible.gitignore")),
+    ("docker", include_str!("../templates/docker.gitignore")),
+    ("vscode", include_str!("../templates/vscode.gitignore")),
+    ("intellij", include_str!("../templates/intellij.gitignore")),
+    ("vim", include_str!("../templates/vim.gitignore")),
+    ("emacs", include_str!("../templates/emacs.gitignore")),
+    ("linux", include_str!("../templates/linux.gitignore")),
+    ("macos", include_str!("../templates/macos.gitignore")),
+    ("windows", include_str!("../templates/windows.gitignore")),
+];
+
+/// Name of the section holding `templates.extra_patterns` and `--pattern`
+pub const CUSTOM_SECTION: &str = "custom";
+
+/// Directive that expands other templates in place
+pub const INCLUDE_DIRECTIVE: &str = "#!include";
+
+/// Names of the sources used when `templates.sources` is empty
+pub const CUSTOM_SOURCE: &str = "custom";
+pub const SYNCED_SOURCE: &str = "synced";
+pub const EMBEDDED_SOURCE: &str = "embedded";
+
+/// Subdirectory of the data and cache directories holding configured synced sources
+const SOURCES_DIR: &str = "sources";
+
+/// How a template source is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceKind {
+    /// A directory of `<name>.gitignore` files
+    Dir,
+    /// A gitignore.io-compatible API, downloaded by `sync`
+    HttpApi,
+    /// A github/gitignore-style repository, downloaded by `sync`
+    GitTarball,
+    /// The templates compiled into the binary
+    Embedded,
+}
+
+impl SourceKind {
+    /// Whether `sync` downloads the templates of this kind of source
+    pub fn is_synced(self) -> bool {
+        matches!(self, SourceKind::HttpApi | SourceKind::GitTarball)
+    }
+}
+
+impl fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SourceKind::Dir => "dir",
+            SourceKind::HttpApi => "http-api",
+            SourceKind::GitTarball => "git-tarball",
+            SourceKind::Embedded => "embedded",
+        })
+    }
+}
+
+/// A place templates are loaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub name: String,
+    pub kind: SourceKind,
+    /// Directory, API URL, or repository archive/checkout, depending on `kind`
+    pub location: Option<String>,
+    pub priority: i64,
+    /// Lowercased prefix selecting this source, as in `corp/python`
+    pub namespace: Option<String>,
+    /// Where a synced source is stored, relative to the data and cache directories
+    store: PathBuf,
+}
+
+impl Source {
+    fn new(name: &str, kind: SourceKind, location: Option<String>, priority: i64) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            location,
+            priority,
+            namespace: None,
+            store: PathBuf::new(),
+        }
+    }
+
+    /// The staging and install directories `sync` uses for this source
+    pub fn sync_layout(&self, data_dir: &Path, cache_dir: &Path) -> SyncLayout {
+        SyncLayout::new(&data_dir.join(&self.store), &cache_dir.join(&self.store))
+    }
+
+    /// Whether `namespace` (from a `namespace/template` name) selects this source
+    fn matches(&self, namespace: Option<&str>) -> bool {
+        namespace.is_none_or(|namespace| self.namespace.as_deref() == Some(namespace))
+    }
+
+    /// Directory holding the source's `.gitignore` files
+    fn dir(&self, data_dir: &Path) -> Option<PathBuf> {
+        match self.kind {
+            SourceKind::Dir => expand_str_path(self.location.as_deref()?).ok(),
+            SourceKind::HttpApi | SourceKind::GitTarball => {
+                Some(data_dir.join(&self.store).join(sync::TEMPLATES_DIR))
+            }
+            SourceKind::Embedded => None,
+        }
+    }
+
+    /// Names of the templates the source currently provides
+    fn template_names(&self, data_dir: &Path) -> BTreeSet<String> {
+        if self.kind == SourceKind::Embedded {
+            return EMBEDDED_TEMPLATES
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect();
+        }
+        let Some(entries) = self.dir(data_dir).and_then(|dir| fs::read_dir(dir).ok()) else {
+            return BTreeSet::new();
+        };
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("gitignore") {
+                    return None;
+                }
+                path.file_stem()
+                    .and_then(|n| n.to_str())
+                    .map(str::to_string)
+            })
+            .collect()
+    }
+
+    /// Loads `template` from the source, naming the result `name`
+    fn load(&self, data_dir: &Path, name: &str, template: &str) -> Option<ResolvedTemplate> {
+        if self.kind == SourceKind::Embedded {
+            let (_, content) = EMBEDDED_TEMPLATES
+                .iter()
+                .find(|(embedded, _)| embedded.eq_ignore_ascii_case(template))?;
+            return Some(ResolvedTemplate {
+                name: name.to_string(),
+                source: self.name.clone(),
+                path: None,
+                content: content.to_string(),
+            });
+        }
+        let path = self.dir(data_dir)?.join(format!("{template}.gitignore"));
+        let content = fs::read_to_string(&path).ok()?;
+        Some(ResolvedTemplate {
+            name: name.to_string(),
+            source: self.name.clone(),
+            path: Some(path),
+            content,
+        })
+    }
+}
+
+/// The template sources in the order they are consulted, highest priority first.
+///
+/// Sources with equal priority keep their configured order. The embedded
+/// templates are consulted last unless a source of kind `embedded` is configured.
+pub fn sources(config: &AppConfig) -> Vec<Source> {
+    let templates = &config.templates;
+    let mut sources: Vec<Source> = if templates.sources.is_empty() {
+        let custom_priority = if templates.prefer_local { 30 } else { 0 };
+        let mut legacy = vec![
+            Source::new(
+                SYNCED_SOURCE,
+                SourceKind::HttpApi,
+                templates.template_url.clone(),
+                20,
+            ),
+            Source::new(EMBEDDED_SOURCE, SourceKind::Embedded, None, 10),
+        ];
+        if templates.template_dir.is_some() {
+            legacy.push(Source::new(
+                CUSTOM_SOURCE,
+                SourceKind::Dir,
+                templates.template_dir.clone(),
+                custom_priority,
+            ));
+        }
+        legacy
+    } else {
+        let mut configured: Vec<Source> = templates
+            .sources
+            .iter()
+            .map(|source| Source {
+                name: source.name.clone(),
+                kind: source.kind,
+                location: source.location.clone(),
+                priority: source.priority,
+                namespace: source.namespace.as_deref().map(str::to_lowercase),
+                store: Path::new(SOURCES_DIR).join(&source.name),
+            })
+            .collect();
+        if !configured.iter().any(|s| s.kind == SourceKind::Embedded) {
+            configured.push(Source::new(
+                EMBEDDED_SOURCE,
+                SourceKind::Embedded,
+                None,
+                i64::MIN,
+            ));
+        }
+        configured
+    };
+    sources.sort_by_key(|source| std::cmp::Reverse(source.priority));
+    sources
+}
+
+/// Checks `[[templates.sources]]` for problems `sources` would otherwise paper over
+pub fn check_sources(sources: &[SourceConfig]) -> Result<()> {
+    let mut names = BTreeSet::new();
+    for source in sources {
+        let name = &source.name;
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow!(
+                "template source name '{name}' may only contain letters, digits, '-' and '_'"
+            ));
+        }
+        if name == EMBEDDED_SOURCE && source.kind != SourceKind::Embedded {
+            return Err(anyhow!(
+                "template source name '{name}' is reserved for the embedded templates"
+            ));
+        }
+        if !names.insert(name.as_str()) {
+            return Err(anyhow!("template source '{name}' is defined twice"));
+        }
+        if let Some(ref namespace) = source.namespace
+            && (namespace.is_empty() || namespace.contains('/'))
+        {
+            return Err(anyhow!(
+                "template source '{name}' has an invalid namespace '{namespace}'"
+            ));
+        }
+        match (source.kind, &source.location) {
+            (SourceKind::Dir | SourceKind::HttpApi, None) => {
+                return Err(anyhow!(
+                    "template source '{name}' ({}) needs a location",
+                    source.kind
+                ));
+            }
+            (SourceKind::Embedded, Some(_)) => {
+                return Err(anyhow!(
+                    "template source '{name}' (embedded) does not take a location"
+                ));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Splits `namespace/template` into its parts
+fn split_namespace(name: &str) -> (Option<&str>, &str) {
+    match name.split_once('/') {
+        Some((namespace, template)) => (Some(namespace), template),
+        None => (None, name),
+    }
+}
+
+/// A template together with where it came from
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedTemplate {
+    pub name: String,
+    /// Name of the source the template was loaded from
+    pub source: String,
+    /// File the template was read from (`None` for embedded templates)
+    pub path: Option<PathBuf>,
+    pub content: String,
+}
+
+impl ResolvedTemplate {
+    pub fn hash(&self) -> String {
+        lock::content_hash(&self.content)
+    }
+
+    /// The template content without its front-matter
+    pub fn body(&self) -> &str {
+        meta::body(&self.content)
+    }
+
+    /// Parses the template's front-matter, if it has any
+    pub fn meta(&self) -> Result<Option<TemplateMeta>> {
+        let (meta, _) = meta::split_front_matter(&self.content)
+            .with_context(|| format!("in template '{}'", self.name))?;
+        Ok(meta)
+    }
+}
+
+/// An available template and its metadata, as shown by `list`
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateInfo {
+    pub name: String,
+    pub source: String,
+    #[serde(flatten)]
+    pub meta: TemplateMeta,
+}
+
+/// Output of merging a set of templates
+#[derive(Debug, Clone, Default)]
+pub struct Merged {
+    pub content: String,
+    /// The template versions that went into `content`, in merge order
+    pub resolved: Vec<ResolvedTemplate>,
+}
+
+/// Minimum similarity for a template name to be suggested for an unknown one
+const SUGGESTION_THRESHOLD: f64 = 0.8;
+/// Maximum number of suggestions offered for an unknown template
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Resolves template names and aliases, and suggests names for typos
+#[derive(Debug, Clone, Default)]
+pub struct NameIndex {
+    names: BTreeSet<String>,
+    /// Alias to template name
+    aliases: BTreeMap<String, String>,
+    /// Config aliases whose target is not an available template
+    broken: BTreeMap<String, String>,
+}
+
+impl NameIndex {
+    /// The template `name` refers to, either directly or through an alias
+    pub fn canonical(&self, name: &str) -> Option<String> {
+        let name = name.to_lowercase();
+        if self.names.contains(&name) {
+            return Some(name);
+        }
+        self.aliases.get(&name).cloned()
+    }
+
+    /// Templates whose name or alias is similar to `name`, best match first
+    pub fn suggestions(&self, name: &str) -> Vec<String> {
+        let name = name.to_lowercase();
+        let mut scored: Vec<(f64, &String)> = self
+            .names
+            .iter()
+            .map(|candidate| (candidate, candidate))
+            .chain(self.aliases.iter())
+            .map(|(candidate, target)| (strsim::jaro_winkler(&name, candidate), target))
+            .filter(|(score, _)| *score >= SUGGESTION_THRESHOLD)
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+
+        let mut suggestions: Vec<String> = Vec::new();
+        for (_, target) in scored {
+            if !suggestions.contains(target) {
+                suggestions.push(target.clone());
+            }
+        }
+        suggestions.truncate(MAX_SUGGESTIONS);
+        suggestions
+    }
+
+    /// "Template 'x' not found" with "did you mean" suggestions appended.
+    ///
+    /// Names a config alias pointing to an unknown template instead, if `name` is one.
+    pub fn not_found_message(&self, name: &str) -> String {
+        let (message, suggestions) = match self.broken.get(&name.to_lowercase()) {
+            Some(target) => (
+                format!("Alias '{name}' points to unknown template '{target}'"),
+                self.suggestions(target),
+            ),
+            None => (
+                format!("Template '{name}' not found"),
+                self.suggestions(name),
+            ),
+        };
+        if suggestions.is_empty() {
+            message
+        } else {
+            let quoted: Vec<String> = suggestions.iter().map(|s| format!("'{s}'")).collect();
+            format!("{message}; did you mean {}?", quoted.join(" or "))
+        }
+    }
+}
+
+/// Template manager for loading and merging templates
+pub struct TemplateManager<'a> {
+    config: &'a AppConfig,
+    data_dir: &'a Path,
+    /// Highest priority first
+    sources: Vec<Source>,
+    lock: Option<&'a Lockfile>,
+    /// Fail instead of warning when a locked template version is unavailable
+    locked: bool,
+}
+
+impl<'a> TemplateManager<'a> {
+    pub fn new(config: &'a AppConfig, data_dir: &'a Path) -> Self {
+        Self {
+            config,
+            data_dir,
+            sources: sources(config),
+            lock: None,
+            locked: false,
+        }
+    }
+
+    /// Pins template versions to the ones recorded in a lockfile
+    pub fn with_lock(mut self, lock: Option<&'a Lockfile>, locked: bool) -> Self {
+        self.lock = lock;
+        self.locked = locked;
+        self
+    }
+
+    /// Names of the templates provided by any source, without namespaces
+    pub fn list_available(&self) -> Vec<String> {
+        let templates: BTreeSet<String> = self
+            .sources
+            .iter()
+            .flat_map(|source| source.template_names(self.data_dir))
+            .collect();
+        templates.into_iter().collect()
+    }
+
+    /// Every available template with the metadata of its highest priority copy.
+    ///
+    /// Copies without front-matter (e.g. templates synced from upstream) fall
+    /// back to the metadata of a lower priority copy.
+    pub fn describe_available(&self) -> Vec<TemplateInfo> {
+        self.list_available()
+            .into_iter()
+            .filter_map(|name| {
+                let candidates = self.candidates(&name);
+                let source = candidates.first()?.source.clone();
+                let meta = candidates
+                    .iter()
+                    .find_map(|candidate| match candidate.meta() {
+                        Ok(meta) => meta,
+                        Err(err) => {
+                            warn!("{err:#}");
+                            None
+                        }
+                    })
+                    .unwrap_or_default();
+                Some(TemplateInfo { name, source, meta })
+            })
+            .collect()
+    }
+
+    /// Builds the name index from the available templates, the aliases in their
+    /// front-matter and `templates.aliases` (which takes precedence)
+    pub fn name_index(&self) -> NameIndex {
+        let mut index = NameIndex::default();
+        for info in self.describe_available() {
+            for alias in &info.meta.aliases {
+                index
+                    .aliases
+                    .entry(alias.to_lowercase())
+                    .or_insert_with(|| info.name.clone());
+            }
+            index.names.insert(info.name);
+        }
+        for source in &self.sources {
+            if let Some(ref namespace) = source.namespace {
+                for name in source.template_names(self.data_dir) {
+                    index.names.insert(format!("{namespace}/{name}"));
+                }
+            }
+        }
+        for (alias, target) in &self.config.templates.aliases {
+            let (alias, target) = (alias.to_lowercase(), target.to_lowercase());
+            if index.names.contains(&target) {
+                index.aliases.insert(alias, target);
+            } else {
+                index.aliases.remove(&alias);
+                index.broken.insert(alias, target);
+            }
+        }
+        index
+    }
+
+    /// Every available copy of a template, highest priority first.
+    ///
+    /// A `namespace/template` name only considers the sources in that namespace.
+    pub fn candidates(&self, name: &str) -> Vec<ResolvedTemplate> {
+        let name_lower = name.to_lowercase();
+        let (namespace, template) = split_namespace(&name_lower);
+        self.sources
+            .iter()
+            .filter(|source| source.matches(namespace))
+            .filter_map(|source| source.load(self.data_dir, &name_lower, template))
+            .collect()
+    }
+
+    /// Resolves the copy of a template to use, honoring the lockfile if any
+    pub fn resolve(&self, name: &str) -> Result<Option<ResolvedTemplate>> {
+        self.resolve_below(name, None)
+    }
+
+    /// Resolves the copy of a template shadowed by the one from `source`,
+    /// i.e. the copy from a lower-priority source, honoring the lockfile
+    fn resolve_shadowed(&self, name: &str, source: &str) -> Result<Option<ResolvedTemplate>> {
+        self.resolve_below(name, Some(source))
+    }
+
+    /// Resolves `name` from the sources after `source` (all sources if `None`).
+    ///
+    /// The lockfile entry used is the one from the highest-priority source
+    /// in that range; entries from unconfigured sources rank last.
+    fn resolve_below(&self, name: &str, source: Option<&str>) -> Result<Option<ResolvedTemplate>> {
+        let rank = |source: &str| {
+            self.sources
+                .iter()
+                .position(|s| s.name == source)
+                .unwrap_or(self.sources.len())
+        };
+        let floor = source.map_or(0, |source| rank(source) + 1);
+        let mut candidates: Vec<_> = self
+            .candidates(name)
+            .into_iter()
+            .filter(|c| rank(&c.source) >= floor)
+            .collect();
+
+        let Some(pinned) = self.lock.and_then(|lock| {
+            lock.entries(name)
+                .into_iter()
+                .filter(|p| rank(&p.source) >= floor)
+                .min_by_key(|p| rank(&p.source))
+        }) else {
+            return Ok(candidates.into_iter().next());
+        };
+
+        // Prefer the locked source, but accept identical content from any source
+        let matches = |c: &ResolvedTemplate| c.hash() == pinned.sha256;
+        if let Some(index) = candidates
+            .iter()
+            .position(|c| c.source == pinned.source && matches(c))
+            .or_else(|| candidates.iter().position(matches))
+        {
+            return Ok(Some(candidates.swap_remove(index)));
+        }
+
+        if self.locked {
+            return Err(anyhow!(
+                "template '{name}' no longer matches the version in {LOCK_FILE} \
+                 (locked from {}); run generate with --update-lock to accept the change",
+                pinned.source
+            ));
+        }
+
+        let fallback = candidates.into_iter().next();
+        match fallback {
+            Some(ref found) => warn!(
+                "Template '{name}' differs from the version in {LOCK_FILE} (locked from {}); using {} copy",
+                pinned.source, found.source
+            ),
+            None => warn!("Template '{name}' from {LOCK_FILE} is no longer available"),
+        }
+        Ok(fallback)
+    }
+
+    /// Merges `templates` in order, followed by a `custom` section holding `patterns`
+    pub fn merge_templates(&self, templates: &[String], patterns: &[String]) -> Result<Merged> {
+        let mut sections = Vec::new();
+        let mut resolved = Vec::new();
+
+        for template_name in templates {
+            if let Some(template) = self.resolve(template_name)? {
+                log_resolved(&template);
+                let mut stack = vec![template.name.clone()];
+                let content = self.expand_includes(&template, &mut stack, &mut resolved)?;
+                sections.push(Section {
+                    name: template_name.clone(),
+                    content,
+                });
+                push_resolved(&mut resolved, template);
+            } else {
+                warn!("Template '{}' not found", template_name);
+            }
+        }
+        if !patterns.is_empty() {
+            sections.push(Section {
+                name: CUSTOM_SECTION.to_string(),
+                content: patterns.join("\n"),
+            });
+        }
+
+        let output = merge::merge(&sections);
+        for collapsed in &output.collapsed {
+            info!(
+                "Collapsed '{}' ({}) into '{}' ({})",
+                collapsed.pattern, collapsed.template, collapsed.kept, collapsed.kept_template
+            );
+        }
+        for conflicts in output.conflicts.chunk_by(|a, b| {
+            (&a.template, &a.ignoring_template) == (&b.template, &b.ignoring_template)
+        }) {
+            let details: Vec<String> = conflicts
+                .iter()
+                .map(|c| {
+                    if c.negation_wins {
+                        format!("'{}' overrides '{}'", c.negation, c.pattern)
+                    } else {
+                        format!("'{}' overrides '{}'", c.pattern, c.negation)
+                    }
+                })
+                .collect();
+            warn!(
+                "Template '{}' un-ignores paths that '{}' ignores: {}",
+                conflicts[0].template,
+                conflicts[0].ignoring_template,
+                details.join(", ")
+            );
+        }
+
+        Ok(Merged {
+            content: output.content,
+            resolved,
+        })
+    }
+
+    /// Replaces include directives in `template` with the included templates.
+    ///
+    /// `stack` holds the chain of templates being expanded and is used to
+    /// detect cycles; every included template is recorded in `resolved`.
+    /// A self-named include expands the shadowed copy, recorded by its source.
+    fn expand_includes(
+        &self,
+        template: &ResolvedTemplate,
+        stack: &mut Vec<String>,
+        resolved: &mut Vec<ResolvedTemplate>,
+    ) -> Result<String> {
+        let mut output = String::new();
+        for line in template.body().lines() {
+            let Some(names) = parse_include(line) else {
+                output.push_str(line);
+                output.push('\n');
+                continue;
+            };
+
+            for name in names {
+                let (_, own_name) = split_namespace(&template.name);
+                if name == own_name {
+                    let Some(shadowed) = self.resolve_shadowed(&name, &template.source)? else {
+                        return Err(anyhow!(
+                            "template '{}' ({}) includes itself, but no lower-priority source provides '{name}'",
+                            template.name,
+                            template.source
+                        ));
+                    };
+                    log_resolved(&shadowed);
+                    output.push_str(&self.expand_includes(&shadowed, stack, resolved)?);
+                    push_resolved(resolved, shadowed);
+                    continue;
+                }
+                if stack.contains(&name) {
+                    return Err(anyhow!(
+                        "template include cycle: {} -> {name}",
+                        stack.join(" -> ")
+                    ));
+                }
+                let Some(included) = self.resolve(&name)? else {
+                    return Err(anyhow!(
+                        "template '{}' includes '{name}', which was not found",
+                        stack.last().map(String::as_str).unwrap_or_default()
+                    ));
+                };
+                log_resolved(&included);
+
+                stack.push(name);
+                let expanded = self.expand_includes(&included, stack, resolved)?;
+                stack.pop();
+
+                output.push_str(&expanded);
+                push_resolved(resolved, included);
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// Parses `#!include a b,c` into the lowercased template names
+fn parse_include(line: &str) -> Option<Vec<String>> {
+    let rest = line.trim().strip_prefix(INCLUDE_DIRECTIVE)?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(
+        rest.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|name| !name.is_empty())
+            .map(str::to_lowercase)
+            .collect(),
+    )
+}
+
+fn push_resolved(resolved: &mut Vec<ResolvedTemplate>, template: ResolvedTemplate) {
+    if !resolved
+        .iter()
+        .any(|t| t.name == template.name && t.source == template.source)
+    {
+        resolved.push(template);
+    }
+}
+
+fn log_resolved(template: &ResolvedTemplate) {
+    match template.path {
+        Some(ref path) => debug!(
+            "Using {} copy of '{}' from {}",
+            template.source,
+            template.name,
+            path.display()
+        ),
+        None => debug!("Using {} copy of '{}'", template.source, template.name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with(dir: &Path) -> AppConfig {
+        let mut config = AppConfig::default();
+        config.templates.template_dir = Some(dir.display().to_string());
+        config
+    }
+
+    #[test]
+    fn parses_include_directives() {
+        assert_eq!(
+            parse_include("#!include linux, macos python"),
+            Some(vec![
+                "linux".to_string(),
+                "macos".to_string(),
+                "python".to_string()
+            ])
+        );
+        assert_eq!(
+            parse_include("  #!include Linux"),
+            Some(vec!["linux".to_string()])
+        );
+        assert_eq!(parse_include("#!included"), None);
+        assert_eq!(parse_include("# include linux"), None);
+    }
+
+    #[test]
+    fn expands_includes_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("base.gitignore"), "/base\n").unwrap();
+        fs::write(
+            dir.path().join("team.gitignore"),
+            "/before\n#!include base\n/after\n",
+        )
+        .unwrap();
+        let config = config_with(dir.path());
+        let data_dir = dir.path().join("data");
+        let manager = TemplateManager::new(&config, &data_dir);
+
+        let merged = manager.merge_templates(&["team".to_string()], &[]).unwrap();
+        assert_eq!(merged.content, "# === team ===\n/before\n/base\n/after\n");
+        let names: Vec<_> = merged.resolved.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["base", "team"]);
+    }
+
+    #[test]
+    fn self_includes_extend_the_shadowed_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("python.gitignore"),
+            "#!include python\n/notebooks/\n",
+        )
+        .unwrap();
+        let config = config_with(dir.path());
+        let data_dir = dir.path().join("data");
+        let manager = TemplateManager::new(&config, &data_dir);
+
+        let merged = manager
+            .merge_templates(&["python".to_string()], &[])
+            .unwrap();
+        assert!(merged.content.contains("\n__pycache__/\n"));
+        assert!(merged.content.ends_with("/notebooks/\n"));
+        let sources: Vec<_> = merged.resolved.iter().map(|t| t.source.as_str()).collect();
+        assert_eq!(sources, vec![EMBEDDED_SOURCE, CUSTOM_SOURCE]);
+
+        // The lowest-priority copy has nothing left to extend
+        fs::write(dir.path().join("python.gitignore"), "/venv/\n").unwrap();
+        fs::write(dir.path().join("team.gitignore"), "#!include team\n").unwrap();
+        let err = manager
+            .merge_templates(&["team".to_string()], &[])
+            .unwrap_err();
+        assert!(err.to_string().contains("no lower-priority source"));
+    }
+
+    #[test]
+    fn self_includes_pin_the_shadowed_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let synced = dir.path().join("data").join(sync::TEMPLATES_DIR);
+        fs::create_dir_all(&synced).unwrap();
+        fs::write(synced.join("python.gitignore"), "/venv/\n").unwrap();
+        fs::write(
+            dir.path().join("python.gitignore"),
+            "#!include python\n/notebooks/\n",
+        )
+        .unwrap();
+        let config = config_with(dir.path());
+        let data_dir = dir.path().join("data");
+        let python = ["python".to_string()];
+
+        let merged = TemplateManager::new(&config, &data_dir)
+            .merge_templates(&python, &[])
+            .unwrap();
+        let lock = Lockfile::from_resolved(&merged.resolved);
+        let sources: Vec<_> = lock.entries("python").iter().map(|t| &t.source).collect();
+        assert_eq!(sources, [CUSTOM_SOURCE, SYNCED_SOURCE]);
+
+        // A changed shadowed copy fails --locked and is used with a warning otherwise
+        fs::write(synced.join("python.gitignore"), "/env/\n").unwrap();
+        let locked = TemplateManager::new(&config, &data_dir).with_lock(Some(&lock), true);
+        let err = locked.merge_templates(&python, &[]).unwrap_err();
+        assert!(err.to_string().contains("locked from synced"), "{err}");
+
+        let unlocked = TemplateManager::new(&config, &data_dir).with_lock(Some(&lock), false);
+        let merged = unlocked.merge_templates(&python, &[]).unwrap();
+        assert_eq!(merged.content, "# === python ===\n/env/\n/notebooks/\n");
+    }
+
+    #[test]
+    fn include_cycles_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.gitignore"), "#!include b\n").unwrap();
+        fs::write(dir.path().join("b.gitignore"), "#!include a\n").unwrap();
+        let config = config_with(dir.path());
+        let data_dir = dir.path().join("data");
+        let manager = TemplateManager::new(&config, &data_dir);
+
+        let err = manager
+            .merge_templates(&["a".to_string()], &[])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "template include cycle: a -> b -> a");
+    }
+
+    #[test]
+    fn resolves_aliases_and_suggests_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = config_with(dir.path());
+        config
+            .templates
+            .aliases
+            .insert("JS".to_string(), "node".to_string());
+        config
+            .templates
+            .aliases
+            .insert("ts".to_string(), "typescript".to_string());
+        config
+            .templates
+            .aliases
+            .insert("py".to_string(), "pyhton".to_string());
+        let data_dir = dir.path().join("data");
+        let index = TemplateManager::new(&config, &data_dir).name_index();
+
+        assert_eq!(index.canonical("Rust").as_deref(), Some("rust"));
+        assert_eq!(index.canonical("c++").as_deref(), Some("cpp"));
+        assert_eq!(index.canonical("jetbrains").as_deref(), Some("intellij"));
+        assert_eq!(index.canonical("osx").as_deref(), Some("macos"));
+        // Config aliases override the ones from front-matter
+        assert_eq!(index.canonical("js").as_deref(), Some("node"));
+        assert_eq!(index.canonical("cobol"), None);
+
+        // Aliases to unknown templates resolve to nothing and say why
+        assert_eq!(index.canonical("ts"), None);
+        assert_eq!(
+            index.not_found_message("ts"),
+            "Alias 'ts' points to unknown template 'typescript'; did you mean 'node'?"
+        );
+        assert_eq!(index.canonical("py"), None);
+        assert_eq!(
+            index.not_found_message("py"),
+            "Alias 'py' points to unknown template 'pyhton'; did you mean 'python'?"
+        );
+
+        assert_eq!(index.suggestions("pyhton"), vec!["python".to_string()]);
+        assert_eq!(index.suggestions("jetbrain")[0], "intellij");
+        assert!(index.suggestions("cobol").is_empty());
+        assert_eq!(
+            index.not_found_message("rustt"),
+            "Template 'rustt' not found; did you mean 'rust'?"
+        );
+    }
+
+    fn source(name: &str, kind: SourceKind, location: Option<&Path>) -> SourceConfig {
+        SourceConfig {
+            name: name.to_string(),
+            kind,
+            location: location.map(|path| path.display().to_string()),
+            priority: 0,
+            namespace: None,
+        }
+    }
+
+    #[test]
+    fn legacy_layers_follow_prefer_local() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = config_with(dir.path());
+        let names = |config: &AppConfig| -> Vec<String> {
+            sources(config).into_iter().map(|s| s.name).collect()
+        };
+        assert_eq!(names(&config), vec!["custom", "synced", "embedded"]);
+        config.templates.prefer_local = false;
+        assert_eq!(names(&config), vec!["synced", "embedded", "custom"]);
+    }
+
+    #[test]
+    fn sources_are_consulted_by_priority_and_namespace() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let synced = data_dir.join("sources/gi/templates");
+        for (path, content) in [
+            ("team/python.gitignore", "/team\n"),
+            ("corp/python.gitignore", "/corp\n"),
+            ("corp/rust.gitignore", "/corp-rust\n"),
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        fs::create_dir_all(&synced).unwrap();
+        fs::write(synced.join("hugo.gitignore"), "/public/\n").unwrap();
+
+        let mut config = AppConfig::default();
+        let mut team = source("team", SourceKind::Dir, Some(&dir.path().join("team")));
+        team.priority = 10;
+        team.namespace = Some("Team".to_string());
+        let mut corp = source("corp", SourceKind::Dir, Some(&dir.path().join("corp")));
+        corp.priority = 20;
+        corp.namespace = Some("corp".to_string());
+        let gi = source(
+            "gi",
+            SourceKind::HttpApi,
+            Some(Path::new("https://example.com")),
+        );
+        config.templates.sources = vec![gi, team, corp];
+        check_sources(&config.templates.sources).unwrap();
+        let manager = TemplateManager::new(&config, &data_dir);
+
+        let order: Vec<_> = manager.sources.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(order, vec!["corp", "team", "gi", "embedded"]);
+
+        let sources_of = |name: &str| -> Vec<String> {
+            manager
+                .candidates(name)
+                .into_iter()
+                .map(|t| t.source)
+                .collect()
+        };
+        assert_eq!(sources_of("python"), vec!["corp", "team", "embedded"]);
+        assert_eq!(sources_of("team/python"), vec!["team"]);
+        assert_eq!(sources_of("corp/rust"), vec!["corp"]);
+        assert!(sources_of("team/rust").is_empty());
+        assert_eq!(sources_of("hugo"), vec!["gi"]);
+
+        let merged = manager
+            .merge_templates(&["team/python".to_string()], &[])
+            .unwrap();
+        assert_eq!(merged.content, "# === team/python ===\n/team\n");
+        assert_eq!(merged.resolved[0].name, "team/python");
+
+        assert!(manager.list_available().contains(&"hugo".to_string()));
+        let index = manager.name_index();
+        assert_eq!(
+            index.canonical("TEAM/Python").as_deref(),
+            Some("team/python")
+        );
+        assert_eq!(index.canonical("corp/hugo"), None);
+    }
+
+    #[test]
+    fn invalid_sources_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let team = source("team", SourceKind::Dir, Some(dir.path()));
+        let check = |sources: &[SourceConfig]| check_sources(sources).unwrap_err().to_string();
+
+        assert_eq!(
+            check(&[team.clone(), team.clone()]),
+            "template source 'team' is defined twice"
+        );
+        assert_eq!(
+            check(&[source("api", SourceKind::HttpApi, None)]),
+            "template source 'api' (http-api) needs a location"
+        );
+        assert_eq!(
+            check(&[source("a/b", SourceKind::Dir, Some(dir.path()))]),
+            "template source name 'a/b' may only contain letters, digits, '-' and '_'"
+        );
+        let mut namespaced = team;
+        namespaced.namespace = Some("a/b".to_string());
+        assert_eq!(
+            check(&[namespaced]),
+            "template source 'team' has an invalid namespace 'a/b'"
+        );
+        assert!(check_sources(&[source("github", SourceKind::GitTarball, None)]).is_ok());
+    }
+
+    #[test]
+    fn extra_patterns_form_a_custom_section() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("team.gitignore"), "/target/\n").unwrap();
+        let config = config_with(dir.path());
+        let data_dir = dir.path().join("data");
+        let manager = TemplateManager::new(&config, &data_dir);
+
+        let patterns = ["*.local.env".to_string(), "/scratch".to_string()];
+        let merged = manager
+            .merge_templates(&["team".to_string()], &patterns)
+            .unwrap();
+        assert_eq!(
+            merged.content,
+            "# === team ===\n/target/\n\n# === custom ===\n*.local.env\n/scratch\n"
+        );
+    }
+
+    #[test]
+    fn front_matter_is_stripped_and_described() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("team.gitignore"),
+            "# ---\n# description: Team defaults\n# tags: [tool]\n# ---\n/team\n",
+        )
+        .unwrap();
+        let config = config_with(dir.path());
+        let data_dir = dir.path().join("data");
+        let manager = TemplateManager::new(&config, &data_dir);
+
+        let merged = manager.merge_templates(&["team".to_string()], &[]).unwrap();
+        assert_eq!(merged.content, "# === team ===\n/team\n");
+
+        let info = manager.describe_available();
+        let team = info.iter().find(|t| t.name == "team").unwrap();
+        assert_eq!(team.source, CUSTOM_SOURCE);
+        assert_eq!(team.meta.description.as_deref(), Some("Team defaults"));
+        assert_eq!(team.meta.tags, vec!["tool"]);
+    }
+}